@@ -17,20 +17,15 @@ You should have received a copy of the GNU General Public License
 along with sirula.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use crate::locale::string_collate;
-use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
-use gio::AppInfo;
-use glib::shell_unquote;
-use gtk::{
-    builders::{BoxBuilder, ImageBuilder, LabelBuilder},
-    prelude::*,
-    IconLookupFlags, IconTheme, Label, ListBoxRow, Orientation,
-};
-use pango::{AttrList, Attribute, EllipsizeMode};
+use crate::locale::{char_to_byte_ranges, string_collate};
+use gio::{AppInfo, DesktopAppInfo};
+use glib::{shell_unquote, Cast};
+use gtk::prelude::*;
+use pango::{AttrList, Attribute};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
-use super::{consts::*, Config, Field, HistoryData};
+use super::{frecency_boost, now_secs, Candidate, Config, Field, History, HistoryData, HistoryKind};
 use regex::RegexSet;
 
 use crate::niri;
@@ -39,36 +34,82 @@ use crate::niri;
 pub struct AppEntry {
     pub display_string: String,
     pub search_string: String,
+    // hidden_fields on their own, each with its weight
+    pub hidden_fields: Vec<(f64, String)>,
     pub extra_range: Option<(u32, u32)>,
-    pub info: AppInfo,
-    pub label: Label,
+    // None for rows that aren't an application: commands and categories
+    pub info: Option<AppInfo>,
+    // Main freedesktop categories of the application
+    pub categories: Vec<String>,
+    // Set on the rows of the category browser
+    pub category: Option<String>,
+    pub icon: Option<gio::Icon>,
     pub score: i64,
+    // Char indices of the display string to highlight
+    pub highlight: Vec<usize>,
+    // Frecency contribution, added to the matcher score when ranking
+    pub boost: i64,
+    // Recomputed on every keystroke from what was picked for the current query
+    pub learned: i64,
     pub history: HistoryData,
+    pub kind: HistoryKind,
+    // Launch entries use the desktop id, windows their niri id
+    pub history_id: String,
+    // Position among the pinned entries
+    pub pinned: Option<usize>,
+    // Past the maximum item count of its section
+    pub over_limit: bool,
     pub custom_cmd: Option<String>,
     pub display: String
 }
 
 impl AppEntry {
-    pub fn update_match(&mut self, pattern: &str, matcher: &SkimMatcherV2, config: &Config) {
-        self.set_markup(config);
-
-        let attr_list = self.label.attributes().unwrap_or_default();
-        self.score = if pattern.is_empty() {
-            self.label.set_attributes(None);
-            100
-        } else if let Some((score, indices)) = matcher.fuzzy_indices(&self.search_string, pattern) {
-            for i in indices {
-                if i < self.display_string.len() {
-                    let i = i as u32;
-                    add_attrs(&attr_list, &config.markup_highlight, i, i + 1);
-                }
+    pub fn app_id(&self) -> Option<String> {
+        self.info.as_ref().and_then(|info| info.id()).map(|id| id.to_string())
+    }
+
+    // Windows are pinned by application, their ids don't survive a restart
+    pub fn pin_key(&self) -> String {
+        if let Some(category) = &self.category {
+            return format!("category:{}", category);
+        }
+        match self.kind {
+            HistoryKind::Focus => format!("window:{}", self.app_id().unwrap_or_default()),
+            HistoryKind::Command => format!("command:{}", self.history_id),
+            HistoryKind::Launch => format!("launch:{}", self.history_id),
+        }
+    }
+
+    pub fn candidate(&self) -> Candidate {
+        Candidate {
+            display: self.display_string.clone(),
+            search: self.search_string.clone(),
+            hidden: self.hidden_fields.clone(),
+        }
+    }
+
+    // Only stores the result, the label is updated if the entry ends up in a row
+    pub fn set_match(&mut self, result: Option<&(i64, Vec<usize>)>) {
+        match result {
+            Some((score, indices)) => {
+                self.score = *score;
+                self.highlight = indices.clone();
             }
-            score
-        } else {
-            0
-        };
+            None => {
+                self.score = 0;
+                self.highlight.clear();
+            }
+        }
+    }
 
-        self.label.set_attributes(Some(&attr_list));
+    // Label attributes for the display string with the current highlight
+    pub fn attributes(&self, config: &Config) -> AttrList {
+        let attr_list = self.base_markup(config);
+        // Indices past the display string come from the joined hidden fields
+        for (start, end) in char_to_byte_ranges(&self.display_string, &self.highlight) {
+            add_attrs(&attr_list, &config.markup_highlight, start, end);
+        }
+        attr_list
     }
 
     pub fn hide(&mut self) {
@@ -76,10 +117,10 @@ impl AppEntry {
     }
 
     pub fn hidden(&self) -> bool {
-        0 == self.score
+        0 == self.score || self.over_limit
     }
 
-    fn set_markup(&self, config: &Config) {
+    fn base_markup(&self, config: &Config) -> AttrList {
         let attr_list = AttrList::new();
 
         add_attrs(
@@ -91,7 +132,7 @@ impl AppEntry {
         if let Some((lo, hi)) = self.extra_range {
             add_attrs(&attr_list, &config.markup_extra, lo, hi);
         }
-        self.label.set_attributes(Some(&attr_list));
+        attr_list
     }
 }
 
@@ -103,10 +144,13 @@ impl PartialEq for AppEntry {
 
 impl Ord for AppEntry {
     fn cmp(&self, other: &Self) -> Ordering {
-        match self.score.cmp(&other.score) {
-            Ordering::Equal => match self.history.usage_count.cmp(&other.history.usage_count) {
-                Ordering::Equal => match self.history.last_used.cmp(&other.history.last_used) {
-                    Ordering::Equal => string_collate(&self.display_string, &other.display_string),
+        match (self.score + self.boost + self.learned).cmp(&(other.score + other.boost + other.learned)) {
+            Ordering::Equal => match (self.kind == HistoryKind::Focus).cmp(&(other.kind == HistoryKind::Focus)) {
+                Ordering::Equal => match self.history.usage_count.cmp(&other.history.usage_count) {
+                    Ordering::Equal => match self.history.last_used.cmp(&other.history.last_used) {
+                        Ordering::Equal => string_collate(&self.display_string, &other.display_string),
+                        ord => ord.reverse(),
+                    },
                     ord => ord.reverse(),
                 },
                 ord => ord.reverse(),
@@ -140,7 +184,53 @@ fn get_app_field(app: &AppInfo, field: Field) -> Option<String> {
             .map(|s| s.to_string_lossy().to_string()),
         //TODO: clean up command line from % for all what is not done in launch_app() in src/util.rx
         Field::Commandline => app.commandline().map(|s| s.to_string_lossy().to_string()),
+        Field::Keywords => desktop_info(app).and_then(|info| {
+            let mut keywords: Vec<String> = info.keywords().iter().map(|k| k.to_string()).collect();
+            // Untranslated ones too, "browser" should work whatever the locale
+            if let Some(raw) = info.string("Keywords") {
+                keywords.extend(raw.split(';').map(str::to_string));
+            }
+            join_unique(keywords)
+        }),
+        Field::GenericName => desktop_info(app).and_then(|info| {
+            join_unique(
+                info.generic_name()
+                    .into_iter()
+                    .chain(info.string("GenericName"))
+                    .map(|s| s.to_string())
+                    .collect(),
+            )
+        }),
+        Field::Categories => desktop_info(app)
+            .and_then(|info| info.categories())
+            .and_then(|c| join_unique(c.split(';').map(str::to_string).collect())),
+        // The Name key as written, without the [it] and friends
+        Field::NameUnlocalized => desktop_info(app).and_then(|info| info.string("Name")).map(Into::into),
+    }
+}
+
+fn desktop_info(app: &AppInfo) -> Option<&DesktopAppInfo> {
+    app.downcast_ref::<DesktopAppInfo>()
+}
+
+fn join_unique(values: Vec<String>) -> Option<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for value in values {
+        let value = value.trim().to_string();
+        if !value.is_empty() && !unique.iter().any(|u| u.eq_ignore_ascii_case(&value)) {
+            unique.push(value);
+        }
     }
+    if unique.is_empty() { None } else { Some(unique.join(" ")) }
+}
+
+fn get_hidden_fields(app: &AppInfo, config: &Config) -> Vec<(f64, String)> {
+    config
+        .hidden_fields
+        .iter()
+        .filter_map(|f| get_app_field(app, *f).map(|s| (config.field_weight(f.key()), s)))
+        .filter(|(_, s)| !s.is_empty())
+        .collect()
 }
 
 fn add_attrs(list: &AttrList, attrs: &Vec<Attribute>, start: u32, end: u32) {
@@ -175,11 +265,12 @@ fn find_app_by_displayname(app_name: &str) -> Option<AppInfo> {
 
 pub fn load_entries_running(
     config: &Config,
+    history: &History,
     windows: Vec<niri::NiriWindow>,
     workspaces_map: HashMap<u8, niri::NiriWorkspace>
-) -> HashMap<ListBoxRow, AppEntry> {
-    let mut entries = HashMap::new();
-    let icon_theme = IconTheme::default().unwrap();
+) -> Vec<AppEntry> {
+    let mut entries = Vec::new();
+    let now = now_secs();
 
     for window in windows {
         /* println!(
@@ -190,7 +281,8 @@ pub fn load_entries_running(
         ); */
 
 
-        let name = window.title.clone().unwrap();
+        // Some windows have no title (yet)
+        let name = window.title.clone().unwrap_or_else(|| window.app_id.clone());
         let app: AppInfo;
         match find_app_by_id(&window.app_id) {
             Some(res) => { app = res }
@@ -198,7 +290,7 @@ pub fn load_entries_running(
                 match find_app_by_displayname(&window.app_id) {
                     Some(res) => { app = res }
                     _ => {
-                        println!("\x1b[91mAppInfo not found with app_id {}\x1b[0m", &window.app_id);
+                        log_debug!("AppInfo not found with app_id {}", &window.app_id);
                         continue;
                     }
                 }
@@ -218,7 +310,7 @@ pub fn load_entries_running(
         let (display_string, extra_range) = if let Some(name) =
             get_app_field(&app, Field::Id).and_then(|id| config.name_overrides.get(&id))
         {
-            println!("creating name {}", name);
+            log_debug!("creating name {}", name);
             let i = name.find('\r');
             (
                 name.replace('\r', " "),
@@ -229,7 +321,7 @@ pub fn load_entries_running(
                 .extra_field
                 .get(0)
                 .and_then(|f| get_app_field(&app, *f));
-            println!("no name for {:?} {:?}", window.title.clone().unwrap(), &extra);
+            log_debug!("no name for {:?} {:?}", window.title.clone().unwrap(), &extra);
             match extra {
                 Some(e) if (!config.hide_extra_if_contained || !name.to_lowercase().contains(&e.to_lowercase())) =>
                 {
@@ -247,9 +339,9 @@ pub fn load_entries_running(
                 }
                 _ => (name, None),
             } */
-           let windata = format!("{} Is open in workspace {}", (if workspaces_map[&window.workspace_id].output == "eDP-1" { "󰌢" } else { "󰍹" }), window.workspace_id);
+           let windata = format!("{} Is open in workspace {}", (if workspaces_map.get(&window.workspace_id).map_or(false, |ws| ws.output == "eDP-1") { "󰌢" } else { "󰍹" }), window.workspace_id);
            (
-                format!("{}\n{}", window.title.unwrap(), windata),
+                format!("{}\n{}", name, windata),
                 Some((
                     name.len() as u32 + 1,
                     name.len() as u32 + 1 + windata.len() as u32,
@@ -259,11 +351,11 @@ pub fn load_entries_running(
 
         // display_string = format!("{} {}", (if workspaces_map[&window.workspace_id].output == "eDP-1" { "󰌢" } else { "󰍹" }), display_string);
 
-        let hidden = config
-            .hidden_fields
+        let hidden_fields = get_hidden_fields(&app, config);
+        let hidden = hidden_fields
             .iter()
-            .map(|f| get_app_field(&app, *f).unwrap_or_default())
-            .collect::<Vec<String>>()
+            .map(|(_, s)| s.as_str())
+            .collect::<Vec<&str>>()
             .join(" ");
 
         let search_string = if hidden.is_empty() {
@@ -272,66 +364,43 @@ pub fn load_entries_running(
             format!("{} {}", display_string, hidden)
         };
 
-        let label = LabelBuilder::new()
-            .xalign(0.0f32)
-            .label(&display_string)
-            .wrap(true)
-            .ellipsize(EllipsizeMode::End)
-            .lines(config.lines)
-            .build();
-        label.style_context().add_class(APP_LABEL_CLASS);
-        label.style_context().add_class("running");
-
-        let image = ImageBuilder::new().pixel_size(config.icon_size).build();
-        if let Some(icon) = app.icon() {
-            // Don't set the icon if it'd give us an ugly fallback icon
-            if icon_theme
-                .lookup_by_gicon(&icon, config.icon_size, IconLookupFlags::FORCE_SIZE)
-                .is_some()
-            {
-                image.set_from_gicon(&icon, gtk::IconSize::Menu);
-            }
-        }
-        image.style_context().add_class(APP_ICON_CLASS);
+        let icon = app.icon();
 
-        let hbox = BoxBuilder::new()
-            .orientation(Orientation::Horizontal)
-            .build();
-        hbox.pack_start(&image, false, false, 0);
-        hbox.pack_end(&label, true, true, 0);
-
-        let row = ListBoxRow::new();
-        row.add(&hbox);
-        row.style_context().add_class(APP_ROW_CLASS);
-
-        /* let history_data = history.get(&id).copied().unwrap_or_default();
-        let last_used = if config.recent_first {
-            history_data.last_used
+        // Sections::compare lists them most recently focused first; the window that already has focus is the least useful target
+        let history_id = window.id.to_string();
+        let history_data = if window.is_focused {
+            HistoryData::default()
         } else {
-            0
+            history.focus.get(&history_id).copied().unwrap_or_default()
         };
-        let usage_count = if config.frequent_first {
-            history_data.usage_count
-        } else {
-            0
-        }; */
+        let boost = frecency_boost(&history_data, now, config.frecency_half_life, config.frecency_weight).round() as i64;
 
         let app_entry = AppEntry {
             display_string,
             search_string,
+            hidden_fields,
             extra_range,
-            info: app,
-            label,
+            categories: main_categories(&app),
+            category: None,
+            info: Some(app),
+            icon,
             score: 100,
+            highlight: Vec::new(),
+            boost,
+            learned: 0,
             history: HistoryData {
-                last_used: 10000000,
-                usage_count: 10000000,
+                last_used: history_data.last_used,
+                usage_count: 0,
+                frecency: history_data.frecency,
             },
+            kind: HistoryKind::Focus,
+            history_id,
+            pinned: None,
+            over_limit: false,
             custom_cmd: Some(format!("niri msg action focus-window --id {}", window.id)),
-            display: workspaces_map[&window.workspace_id].output.clone()
+            display: workspaces_map.get(&window.workspace_id).map(|ws| ws.output.clone()).unwrap_or_default()
         };
-        app_entry.set_markup(config);
-        entries.insert(row, app_entry);
+        entries.push(app_entry);
     }
 
     entries
@@ -339,12 +408,15 @@ pub fn load_entries_running(
 
 pub fn load_entries(
     config: &Config,
-    history: &HashMap<String, HistoryData>,
-) -> HashMap<ListBoxRow, AppEntry> {
-    let mut entries = HashMap::new();
-    let icon_theme = IconTheme::default().unwrap();
+    history: &History,
+) -> Vec<AppEntry> {
+    let mut entries = Vec::new();
     let apps = gio::AppInfo::all();
-    let exclude = RegexSet::new(&config.exclude).expect("Invalid regex");
+    let exclude = RegexSet::new(&config.exclude).unwrap_or_else(|err| {
+        log_error!("Invalid exclude pattern: {}", err);
+        RegexSet::empty()
+    });
+    let now = now_secs();
 
     for app in apps {
         if !app.should_show() {
@@ -396,11 +468,11 @@ pub fn load_entries(
             }
         };
 
-        let hidden = config
-            .hidden_fields
+        let hidden_fields = get_hidden_fields(&app, config);
+        let hidden = hidden_fields
             .iter()
-            .map(|f| get_app_field(&app, *f).unwrap_or_default())
-            .collect::<Vec<String>>()
+            .map(|(_, s)| s.as_str())
+            .collect::<Vec<&str>>()
             .join(" ");
 
         let search_string = if hidden.is_empty() {
@@ -409,38 +481,9 @@ pub fn load_entries(
             format!("{} {}", display_string, hidden)
         };
 
-        let label = LabelBuilder::new()
-            .xalign(0.0f32)
-            .label(&display_string)
-            .wrap(true)
-            .ellipsize(EllipsizeMode::End)
-            .lines(config.lines)
-            .build();
-        label.style_context().add_class(APP_LABEL_CLASS);
-
-        let image = ImageBuilder::new().pixel_size(config.icon_size).build();
-        if let Some(icon) = app.icon() {
-            // Don't set the icon if it'd give us an ugly fallback icon
-            if icon_theme
-                .lookup_by_gicon(&icon, config.icon_size, IconLookupFlags::FORCE_SIZE)
-                .is_some()
-            {
-                image.set_from_gicon(&icon, gtk::IconSize::Menu);
-            }
-        }
-        image.style_context().add_class(APP_ICON_CLASS);
-
-        let hbox = BoxBuilder::new()
-            .orientation(Orientation::Horizontal)
-            .build();
-        hbox.pack_start(&image, false, false, 0);
-        hbox.pack_end(&label, true, true, 0);
+        let icon = app.icon();
 
-        let row = ListBoxRow::new();
-        row.add(&hbox);
-        row.style_context().add_class(APP_ROW_CLASS);
-
-        let history_data = history.get(&id).copied().unwrap_or_default();
+        let history_data = history.launch.get(&id).copied().unwrap_or_default();
         let last_used = if config.recent_first {
             history_data.last_used
         } else {
@@ -452,22 +495,247 @@ pub fn load_entries(
             0
         };
 
+        let boost = frecency_boost(&history_data, now, config.frecency_half_life, config.frecency_weight).round() as i64;
+
         let app_entry = AppEntry {
             display_string,
             search_string,
+            hidden_fields,
             extra_range,
-            info: app,
-            label,
+            categories: main_categories(&app),
+            category: None,
+            info: Some(app),
+            icon,
             score: 100,
+            highlight: Vec::new(),
+            boost,
+            learned: 0,
             history: HistoryData {
                 last_used,
                 usage_count,
+                frecency: history_data.frecency,
             },
+            kind: HistoryKind::Launch,
+            history_id: id,
+            pinned: None,
+            over_limit: false,
+            custom_cmd: None,
+            display: String::new()
+        };
+        entries.push(app_entry);
+    }
+    entries
+}
+
+pub fn apply_pins(entries: &mut [AppEntry], pinned: &[String]) {
+    for entry in entries.iter_mut() {
+        let key = entry.pin_key();
+        entry.pinned = pinned.iter().position(|k| *k == key);
+    }
+}
+
+// Recent commands, so they can be searched and run again without the prefix
+pub fn load_entries_commands(config: &Config, history: &History) -> Vec<AppEntry> {
+    let mut entries = Vec::new();
+    let now = now_secs();
+
+    for (cmd_line, history_data) in &history.command {
+        let app_entry = AppEntry {
+            display_string: cmd_line.clone(),
+            search_string: cmd_line.clone(),
+            hidden_fields: Vec::new(),
+            extra_range: None,
+            info: None,
+            categories: Vec::new(),
+            category: None,
+            icon: Some(gio::ThemedIcon::new("utilities-terminal").upcast()),
+            score: 100,
+            highlight: Vec::new(),
+            boost: frecency_boost(history_data, now, config.frecency_half_life, config.frecency_weight).round() as i64,
+            learned: 0,
+            history: *history_data,
+            kind: HistoryKind::Command,
+            history_id: cmd_line.clone(),
+            pinned: None,
+            over_limit: false,
             custom_cmd: None,
             display: String::new()
         };
-        app_entry.set_markup(config);
-        entries.insert(row, app_entry);
+        entries.push(app_entry);
     }
     entries
-}
\ No newline at end of file
+}
+
+// Main categories of the freedesktop menu spec, with the icons most themes have for them
+const MAIN_CATEGORIES: [(&str, &str); 13] = [
+    ("AudioVideo", "applications-multimedia"),
+    ("Audio", "audio-x-generic"),
+    ("Video", "video-x-generic"),
+    ("Development", "applications-development"),
+    ("Education", "applications-science"),
+    ("Game", "applications-games"),
+    ("Graphics", "applications-graphics"),
+    ("Network", "applications-internet"),
+    ("Office", "applications-office"),
+    ("Science", "applications-science"),
+    ("Settings", "preferences-desktop"),
+    ("System", "applications-system"),
+    ("Utility", "applications-utilities"),
+];
+
+fn main_categories(app: &AppInfo) -> Vec<String> {
+    desktop_info(app)
+        .and_then(|info| info.categories())
+        .map(|c| {
+            c.split(';')
+                .filter(|c| MAIN_CATEGORIES.iter().any(|(name, _)| name == c))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// One row per category with at least an application, they stay hidden until the category browser shows them
+pub fn load_entries_categories(config: &Config, apps: &[AppEntry]) -> Vec<AppEntry> {
+    let mut entries = Vec::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for app in apps.iter().filter(|e| e.kind == HistoryKind::Launch) {
+        for category in &app.categories {
+            *counts.entry(category.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    for (name, icon) in MAIN_CATEGORIES.iter() {
+        let count = match counts.get(name) {
+            Some(count) => *count,
+            None => continue,
+        };
+        let display_string = format!("{} ({})", name, count);
+
+        let mut app_entry = AppEntry {
+            search_string: name.to_string(),
+            hidden_fields: Vec::new(),
+            extra_range: Some((name.len() as u32 + 1, display_string.len() as u32)),
+            display_string,
+            info: None,
+            categories: Vec::new(),
+            category: Some(name.to_string()),
+            icon: Some(gio::ThemedIcon::new(icon).upcast()),
+            score: 100,
+            highlight: Vec::new(),
+            boost: 0,
+            learned: 0,
+            history: HistoryData::default(),
+            kind: HistoryKind::Launch,
+            history_id: format!("category:{}", name),
+            pinned: None,
+            over_limit: false,
+            custom_cmd: None,
+            display: String::new()
+        };
+        if !config.categories_on_empty {
+            app_entry.hide();
+        }
+        entries.push(app_entry);
+    }
+    entries
+}
+
+const SECTIONS: [&str; 5] = ["pinned", "windows", "applications", "commands", "categories"];
+
+pub struct Sections {
+    pub order: Vec<String>,
+    pub max: HashMap<String, usize>,
+    pub headers: bool,
+}
+
+impl Sections {
+    pub fn from_config(config: &Config) -> Sections {
+        let mut order = config.sections.clone();
+        order.retain(|s| {
+            let known = SECTIONS.contains(&s.as_str());
+            if !known {
+                log_warn!("Unknown section \"{}\" in sections, ignoring it", s);
+            }
+            known
+        });
+        for name in config.section_max.keys().filter(|name| !SECTIONS.contains(&name.as_str())) {
+            log_warn!("Unknown section \"{}\" in section_max", name);
+        }
+        // The others can be left out to hide them, without these the launcher or the category browser would be empty
+        for required in ["applications", "categories"] {
+            if !order.iter().any(|s| s == required) {
+                log_warn!("\"{}\" is missing from sections, adding it at the end", required);
+                order.push(required.into());
+            }
+        }
+        Sections {
+            order,
+            max: config.section_max.clone(),
+            headers: config.section_headers,
+        }
+    }
+
+    fn rank(&self, section: &str) -> Option<usize> {
+        self.order.iter().position(|s| s == section)
+    }
+
+    // Pins only make a section of their own with an empty query
+    pub fn section_of(&self, entry: &AppEntry, query_empty: bool) -> &'static str {
+        if entry.category.is_some() {
+            return "categories";
+        }
+        if query_empty && entry.pinned.is_some() && self.rank("pinned").is_some() {
+            return "pinned";
+        }
+        match entry.kind {
+            HistoryKind::Focus => "windows",
+            HistoryKind::Launch => "applications",
+            HistoryKind::Command => "commands",
+        }
+    }
+
+    // Windows get one header per output
+    pub fn header_of(&self, entry: &AppEntry, query_empty: bool) -> String {
+        match self.section_of(entry, query_empty) {
+            "pinned" => "Pinned".into(),
+            "windows" if entry.display.is_empty() => "Open windows".into(),
+            "windows" => format!("Open windows \u{2014} {}", entry.display),
+            "commands" => "Commands".into(),
+            "categories" => "Categories".into(),
+            _ => "Applications".into(),
+        }
+    }
+
+    pub fn compare(&self, a: &AppEntry, b: &AppEntry, query_empty: bool) -> Ordering {
+        let (section_a, section_b) = (self.section_of(a, query_empty), self.section_of(b, query_empty));
+        match self.rank(section_a).cmp(&self.rank(section_b)) {
+            Ordering::Equal => {}
+            ord => return ord,
+        }
+        match section_a {
+            "pinned" => a.pinned.cmp(&b.pinned),
+            "windows" if a.display != b.display => b.display.cmp(&a.display),
+            // Most recently focused first, typing ranks them by match
+            "windows" if query_empty => b.history.last_used.cmp(&a.history.last_used).then_with(|| a.cmp(b)),
+            _ => a.cmp(b),
+        }
+    }
+
+    // Hides what's past each section's maximum, and whole sections left out of the order
+    pub fn apply_limits(&self, entries: &mut [AppEntry], query_empty: bool) {
+        for entry in entries.iter_mut() {
+            entry.over_limit = false;
+        }
+        let mut visible: Vec<&mut AppEntry> = entries.iter_mut().filter(|e| !e.hidden()).collect();
+        visible.sort_by(|a, b| self.compare(a, b, query_empty));
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for entry in visible {
+            let section = self.section_of(entry, query_empty);
+            let count = counts.entry(section).or_insert(0);
+            *count += 1;
+            let max = self.max.get(section).copied().unwrap_or(usize::MAX);
+            entry.over_limit = self.rank(section).is_none() || *count > max;
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use gio::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::matcher::*;
+use crate::Config;
+
+const USAGE: &str = "Usage: skoll bench <query> [file with one candidate per line]";
+
+// Types the query one char at a time and prints how long matching takes at each keystroke,
+// once against every candidate and once narrowing from the previous results like the launcher does
+pub fn run_bench_command(args: &[String]) -> Option<i32> {
+    if args.get(1).map(String::as_str) != Some("bench") {
+        return None;
+    }
+    let query = match args.get(2) {
+        Some(query) => query,
+        None => {
+            eprintln!("{}", USAGE);
+            return Some(1);
+        }
+    };
+    let candidates = match args.get(3) {
+        Some(file) => match std::fs::read_to_string(file) {
+            Ok(s) => s.lines().map(candidate).collect(),
+            Err(err) => {
+                eprintln!("Cannot read {}: {}", file, err);
+                return Some(1);
+            }
+        },
+        None => gio::AppInfo::all().iter().map(|app| candidate(&app.display_name())).collect::<Vec<_>>(),
+    };
+
+    let matcher = Matcher::from_config(&Config::load());
+    let full_mask = vec![true; candidates.len()];
+    let mut narrowed_mask = full_mask.clone();
+    let mut previous: Option<Query> = None;
+    let (mut total_full, mut total_narrowed) = (Duration::ZERO, Duration::ZERO);
+
+    println!("{} candidates", candidates.len());
+    println!("{:<20} {:>8} {:>12} {:>12}", "query", "matches", "full", "narrowed");
+    for (end, _) in query.char_indices().skip(1).chain(std::iter::once((query.len(), ' '))) {
+        let typed = &query[..end];
+        let parsed = matcher.parse(typed);
+
+        let start = Instant::now();
+        let full = matcher.match_all(&parsed, &candidates, &full_mask);
+        let full_time = start.elapsed();
+
+        if !previous.as_ref().map_or(false, |p| parsed.narrows(p)) {
+            narrowed_mask = full_mask.clone();
+        }
+        let start = Instant::now();
+        let narrowed = matcher.match_all(&parsed, &candidates, &narrowed_mask);
+        let narrowed_time = start.elapsed();
+        narrowed_mask = narrowed.iter().map(Option::is_some).collect();
+        previous = Some(parsed);
+
+        let matches = full.iter().filter(|r| r.is_some()).count();
+        println!("{:<20} {:>8} {:>12?} {:>12?}", typed, matches, full_time, narrowed_time);
+        total_full += full_time;
+        total_narrowed += narrowed_time;
+    }
+    println!("{:<20} {:>8} {:>12?} {:>12?}", "total", "", total_full, total_narrowed);
+    Some(0)
+}
+
+fn candidate(line: &str) -> Candidate {
+    Candidate { display: line.to_string(), search: line.to_string(), hidden: Vec::new() }
+}
@@ -16,9 +16,12 @@ along with sirula.  If not, see <https://www.gnu.org/licenses/>.
 */
 
 use super::consts::*;
+use super::metrics::MetricConfig;
 use super::util::get_config_file;
+use super::weather::WeatherConfig;
 use pango::Attribute;
-use serde::{de::Error, Deserializer};
+use super::error::Error;
+use serde::{de::Error as _, Deserializer};
 use serde_derive::Deserialize;
 use std::collections::HashMap;
 
@@ -42,6 +45,26 @@ pub enum Field {
     IdSuffix,
     Executable,
     Commandline,
+    Keywords,
+    GenericName,
+    Categories,
+    NameUnlocalized,
+}
+
+impl Field {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Field::Comment => "comment",
+            Field::Id => "id",
+            Field::IdSuffix => "id_suffix",
+            Field::Executable => "executable",
+            Field::Commandline => "commandline",
+            Field::Keywords => "keywords",
+            Field::GenericName => "generic_name",
+            Field::Categories => "categories",
+            Field::NameUnlocalized => "name_unlocalized",
+        }
+    }
 }
 
 // not sure how to avoid having to specify the name twice
@@ -53,6 +76,9 @@ make_config!(Config {
     frequent_first: bool = (false) "frequent_first",
     recent_first: bool = (true) "recent_first",
     prune_history: u32 = (0) "prune_history",
+    frecency_weight: f64 = (10.0) "frecency_weight",
+    frecency_half_life: u32 = (14) "frecency_half_life",
+    query_learning_weight: f64 = (30.0) "query_learning_weight",
     icon_size: i32 = (64) "icon_size",
     lines: i32 = (2) "lines",
     margin_left: i32 = (0) "margin_left",
@@ -74,7 +100,29 @@ make_config!(Config {
     command_prefix: String = (":".into()) "command_prefix",
     exclude: Vec<String> = (Vec::new()) "exclude",
     term_command: Option<String> = (None) "term_command",
-    close_on_unfocus: bool = (true) "close_on_unfocus"
+    // Seconds to wait for a launched app to fail before quitting, 0 doesn't wait
+    launch_watch: u64 = (0) "launch_watch",
+    close_on_unfocus: bool = (true) "close_on_unfocus",
+    sections: Vec<String> = (vec!["pinned".into(), "windows".into(), "applications".into(), "commands".into(), "categories".into()]) "sections",
+    section_max: HashMap<String, usize> = (HashMap::new()) "section_max",
+    section_headers: bool = (true) "section_headers",
+    matcher: String = ("fuzzy".into()) "matcher",
+    case_mode: String = ("smart".into()) "case_mode",
+    regex_prefix: String = ("/".into()) "regex_prefix",
+    field_weights: HashMap<String, f64> = (HashMap::new()) "field_weights",
+    fold_diacritics: bool = (true) "fold_diacritics",
+    category_prefix: String = ("@".into()) "category_prefix",
+    categories_on_empty: bool = (false) "categories_on_empty",
+    async_match_threshold: usize = (5000) "async_match_threshold",
+    // 0 builds a row for every entry
+    max_rows: usize = (300) "max_rows",
+    metrics: HashMap<String, MetricConfig> = (HashMap::new()) "metrics",
+    palette: String = ("default".into()) "palette",
+    palette_stops: Vec<String> = (Vec::new()) "palette_stops",
+    palette_css: bool = (false) "palette_css",
+    info_layout: String = ("bar".into()) "info_layout",
+    info_icon_size: i32 = (16) "info_icon_size",
+    weather: WeatherConfig = (WeatherConfig::default()) "weather"
 });
 
 fn deserialize_markup<'de, D>(deserializer: D) -> Result<Vec<Attribute>, D::Error>
@@ -85,14 +133,40 @@ where
     parse_attributes(s).map_err(D::Error::custom)
 }
 
+impl Default for Config {
+    fn default() -> Config {
+        toml::from_str("").expect("Every config field has a default")
+    }
+}
+
 impl Config {
-    pub fn load() -> Config {
+    pub fn try_load() -> Result<Config, Error> {
         let config_str = match get_config_file(CONFIG_FILE) {
-            Some(file) => std::fs::read_to_string(file).expect("Cannot read config"),
+            Some(file) => std::fs::read_to_string(&file)
+                .map_err(|err| Error::io(format!("Cannot read {}", file.display()), err))?,
             _ => "".to_owned(),
         };
-        let config: Config = toml::from_str(&config_str).expect("Cannot parse config: {}");
-        config
+        toml::from_str(&config_str).map_err(|err| Error::parse(CONFIG_FILE, err))
+    }
+
+    // A broken config file shouldn't leave the user without a launcher: the defaults are used instead
+    pub fn load() -> Config {
+        Config::try_load().unwrap_or_else(|err| {
+            log_error!("{}, using the default config", err);
+            Config::default()
+        })
+    }
+
+    // "name" is the displayed string, everything else is one of the hidden_fields
+    pub fn field_weight(&self, field: &str) -> f64 {
+        self.field_weights
+            .get(field)
+            .copied()
+            .unwrap_or(match field {
+                "name" => 1.0,
+                "name_unlocalized" => 0.9,
+                _ => 0.5,
+            })
     }
 }
 
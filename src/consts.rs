@@ -21,11 +21,16 @@ pub const APP_NAME: &str = "skoll";
 pub const STYLE_FILE: &str = "style.css";
 pub const CONFIG_FILE: &str = "config.toml";
 pub const HISTORY_FILE: &str = "history";
+pub const QUERY_HISTORY_FILE: &str = "queries";
+pub const WEATHER_CACHE_FILE: &str = "weather.json";
+pub const ICON_CACHE_FILE: &str = "icons.json";
 
 pub const APP_LABEL_CLASS: &str = "app-label";
 pub const APP_ICON_CLASS: &str = "app-icon";
 pub const APP_ROW_CLASS: &str = "app-row";
+pub const PINNED_ROW_CLASS: &str = "pinned";
 pub const ROOT_BOX_NAME: &str = "root-box";
 pub const LISTBOX_NAME: &str = "app-list";
 pub const SEARCH_ENTRY_NAME: &str = "search";
-pub const SCROLL_NAME: &str = "scroll";
\ No newline at end of file
+pub const SCROLL_NAME: &str = "scroll";
+pub const ERROR_LABEL_NAME: &str = "error";
\ No newline at end of file
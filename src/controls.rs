@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+static BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+pub struct VolumeState {
+    // Percent, above 100 when amplified
+    pub value: u16,
+    pub muted: bool,
+}
+
+pub fn volume_icon(value: u16, muted: bool) -> &'static str {
+    if muted || value == 0 { "󰝟" } else
+    if value < 33 { "󰕿" } else
+    if value < 66 { "󰖀" } else { "󰕾" }
+}
+
+pub fn brightness_icon(percentage: u16) -> &'static str {
+    if percentage < 33 { "󰃞" } else
+    if percentage < 66 { "󰃟" } else { "󰃠" }
+}
+
+fn pactl(args: &[&str]) -> Option<String> {
+    let output = Command::new("pactl").args(args).output().ok()?;
+    if !output.status.success() {
+        log_warn!("pactl {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+pub fn get_volume() -> Option<VolumeState> {
+    // "Volume: front-left: 32768 /  50% / -18,06 dB,   front-right: ..."
+    let volume = pactl(&["get-sink-volume", "@DEFAULT_SINK@"])?;
+    let value = volume
+        .split('/')
+        .nth(1)
+        .and_then(|s| s.trim().trim_end_matches('%').parse::<u16>().ok())?;
+    let muted = pactl(&["get-sink-mute", "@DEFAULT_SINK@"])
+        .map(|s| s.trim().ends_with("yes"))
+        .unwrap_or(false);
+    Some(VolumeState { value, muted })
+}
+
+pub fn change_volume(delta: i32) {
+    let step = format!("{:+}%", delta);
+    // Never go above 100%, pactl would happily amplify
+    if delta > 0 {
+        if let Some(state) = get_volume() {
+            if state.value as i32 + delta > 100 {
+                pactl(&["set-sink-volume", "@DEFAULT_SINK@", "100%"]);
+                return;
+            }
+        }
+    }
+    pactl(&["set-sink-volume", "@DEFAULT_SINK@", &step]);
+}
+
+pub fn toggle_mute() {
+    pactl(&["set-sink-mute", "@DEFAULT_SINK@", "toggle"]);
+}
+
+fn backlight_device() -> Option<PathBuf> {
+    fs::read_dir(BACKLIGHT_DIR)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.join("max_brightness").exists())
+}
+
+fn read_number(path: PathBuf) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn get_brightness() -> Option<u16> {
+    let device = backlight_device()?;
+    let max = read_number(device.join("max_brightness"))?;
+    let current = read_number(device.join("brightness"))?;
+    if max == 0 {
+        return None;
+    }
+    Some((current as f64 / max as f64 * 100.0).round() as u16)
+}
+
+pub fn change_brightness(delta: i32) {
+    let device = match backlight_device() {
+        Some(d) => d,
+        _ => {
+            log_warn!("No backlight device found in {}", BACKLIGHT_DIR);
+            return;
+        }
+    };
+    if let (Some(max), Some(current)) = (
+        read_number(device.join("max_brightness")),
+        read_number(device.join("brightness"))
+    ) {
+        let percentage = (current as f64 / max as f64 * 100.0).round() as i32;
+        // Keep at least 1% so the screen never goes completely dark
+        let target = (percentage + delta).clamp(1, 100);
+        let raw = (target as f64 / 100.0 * max as f64).round() as u32;
+        // Writing requires the user to be in the video group (or an udev rule)
+        if let Err(err) = fs::write(device.join("brightness"), raw.to_string()) {
+            log_warn!("Cannot set brightness: {}", err);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+// Whatever can fail when talking to the outside world: reported in the window and the log, never a panic
+#[derive(Debug)]
+pub enum Error {
+    Io { context: String, source: std::io::Error },
+    Parse { context: String, message: String },
+    Command { command: String, message: String },
+    Launch { app: String, message: String },
+}
+
+impl Error {
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Error {
+        Error::Io { context: context.into(), source }
+    }
+
+    pub fn parse(context: impl Into<String>, message: impl ToString) -> Error {
+        Error::Parse { context: context.into(), message: message.to_string() }
+    }
+
+    pub fn command(command: impl Into<String>, message: impl ToString) -> Error {
+        Error::Command { command: command.into(), message: message.to_string() }
+    }
+
+    pub fn launch(app: impl Into<String>, message: impl ToString) -> Error {
+        Error::Launch { app: app.into(), message: message.to_string() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+            Error::Parse { context, message } => write!(f, "Cannot parse {}: {}", context, message),
+            Error::Command { command, message } => write!(f, "{} failed: {}", command, message),
+            Error::Launch { app, message } => write!(f, "Cannot launch {}: {}", app, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -1,14 +1,23 @@
-use super::util::get_history_file;
+use super::consts::QUERY_HISTORY_FILE;
+use super::util::{get_cache_file, get_history_file, write_atomic};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Copy, Clone, Default, Eq, Deserialize, Serialize)]
+// 1 is the flat sirula map, 2 splits launches, focuses and commands
+pub const HISTORY_VERSION: u32 = 2;
+
+#[derive(Copy, Clone, Default, Deserialize, Serialize)]
 pub struct HistoryData {
     pub last_used: u64,
     pub usage_count: u32,
+    // Visit score as it was at last_used, it decays exponentially from there
+    #[serde(default)]
+    pub frecency: f64,
 }
 
 impl PartialEq for HistoryData {
@@ -17,47 +26,544 @@ impl PartialEq for HistoryData {
     }
 }
 
-pub fn load_history(days: u32) -> HashMap<String, HistoryData> {
+impl Eq for HistoryData {}
+
+impl HistoryData {
+    pub fn frecency_at(&self, now: u64, half_life_days: u32) -> f64 {
+        // Entries written before frecency existed start from their usage count
+        let score = if self.frecency == 0.0 { self.usage_count as f64 } else { self.frecency };
+        if half_life_days == 0 {
+            return score;
+        }
+        let age = now.saturating_sub(self.last_used) as f64;
+        score * 0.5f64.powf(age / (half_life_days as f64 * 86400.0))
+    }
+}
+
+// What gets added to the matcher score: logarithmic, so that a hundred launches don't bury everything else
+pub fn frecency_boost(data: &HistoryData, now: u64, half_life_days: u32, weight: f64) -> f64 {
+    weight * data.frecency_at(now, half_life_days).ln_1p()
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HistoryKind {
+    Launch,
+    Focus,
+    Command,
+}
+
+// Launches are keyed by desktop id, focuses by niri window id, commands by the command line
+#[derive(Default, Deserialize, Serialize)]
+pub struct History {
+    #[serde(default)]
+    pub version: u32,
+    // "launch:<desktop id>" or "window:<desktop id>", in the order they're shown
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    #[serde(default)]
+    pub launch: HashMap<String, HistoryData>,
+    #[serde(default)]
+    pub focus: HashMap<String, HistoryData>,
+    #[serde(default)]
+    pub command: HashMap<String, HistoryData>,
+}
+
+impl History {
+    pub fn kind(&self, kind: HistoryKind) -> &HashMap<String, HistoryData> {
+        match kind {
+            HistoryKind::Launch => &self.launch,
+            HistoryKind::Focus => &self.focus,
+            HistoryKind::Command => &self.command,
+        }
+    }
+
+    pub fn kind_mut(&mut self, kind: HistoryKind) -> &mut HashMap<String, HistoryData> {
+        match kind {
+            HistoryKind::Launch => &mut self.launch,
+            HistoryKind::Focus => &mut self.focus,
+            HistoryKind::Command => &mut self.command,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.launch.is_empty() && self.focus.is_empty() && self.command.is_empty()
+    }
+
+    pub fn record(&mut self, kind: HistoryKind, id: &str, half_life_days: u32) {
+        update_history(self.kind_mut(kind), id, half_life_days);
+    }
+
+    // Window ids are only valid while niri runs, drop the ones that are gone. Returns whether any were
+    pub fn retain_windows(&mut self, ids: &[String]) -> bool {
+        let len = self.focus.len();
+        self.focus.retain(|id, _| ids.contains(id));
+        self.focus.len() != len
+    }
+
+    // Drops the id from every kind, returns whether something was there
+    pub fn forget(&mut self, id: &str) -> bool {
+        let mut found = false;
+        for kind in [HistoryKind::Launch, HistoryKind::Focus, HistoryKind::Command] {
+            found |= self.kind_mut(kind).remove(id).is_some();
+        }
+        found
+    }
+
+    // Another instance may have written in the meantime, for every id the most recent use wins
+    pub fn merge(&mut self, other: History) {
+        for kind in [HistoryKind::Launch, HistoryKind::Focus, HistoryKind::Command] {
+            let map = self.kind_mut(kind);
+            for (id, data) in other.kind(kind) {
+                match map.get(id) {
+                    Some(current) if current.last_used >= data.last_used => {}
+                    _ => {
+                        map.insert(id.clone(), *data);
+                    }
+                }
+            }
+        }
+        for key in other.pinned {
+            if !self.pinned.contains(&key) {
+                self.pinned.push(key);
+            }
+        }
+    }
+
+    pub fn toggle_pin(&mut self, key: &str) {
+        match self.pinned.iter().position(|k| k == key) {
+            Some(i) => {
+                self.pinned.remove(i);
+            }
+            None => self.pinned.push(key.to_string()),
+        }
+    }
+
+    pub fn move_pin(&mut self, key: &str, delta: isize) {
+        if let Some(i) = self.pinned.iter().position(|k| k == key) {
+            let j = (i as isize + delta).clamp(0, self.pinned.len() as isize - 1) as usize;
+            let key = self.pinned.remove(i);
+            self.pinned.insert(j, key);
+        }
+    }
+}
+
+// Held for the whole read-merge-write cycle, released on drop
+struct FileLock(File);
+
+impl FileLock {
+    fn acquire(path: &Path) -> Option<FileLock> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path.with_extension("lock"))
+            .map_err(|err| log_warn!("Cannot open lock file for {}: {}", path.display(), err))
+            .ok()?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            log_warn!("Cannot lock {}: {}", path.display(), std::io::Error::last_os_error());
+            return None;
+        }
+        Some(FileLock(file))
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.0.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+// Keeps the broken file around for inspection, the next save starts from scratch
+fn quarantine(path: &Path, err: &dyn std::fmt::Display) {
+    let target = path.with_extension(format!("corrupt-{}", now_secs()));
+    log_warn!("Cannot parse {}: {}, moving it to {}", path.display(), err, target.display());
+    if let Err(err) = std::fs::rename(path, &target) {
+        log_warn!("Cannot move {}: {}", path.display(), err);
+    }
+}
+
+fn read_cache_file(path: &Path) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => Some(s),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => {
+            log_warn!("Cannot read {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+// Also reads sirula's cache file, which is the unversioned flat map
+pub fn parse_history_str(history_str: &str) -> Result<History, String> {
+    let value: toml::Value = toml::from_str(history_str).map_err(|err| err.to_string())?;
+    let version = value.get("version").and_then(|v| v.as_integer());
+    let parsed = match version {
+        Some(v) => {
+            if v > HISTORY_VERSION as i64 {
+                log_warn!("History file version {} is newer than {}, reading what is known", v, HISTORY_VERSION);
+            }
+            value.try_into::<History>()
+        }
+        // Unversioned: either the sirula flat map or the first split format
+        None => value.clone().try_into::<History>().and_then(|history| {
+            if !history.is_empty() {
+                return Ok(history);
+            }
+            value
+                .try_into::<HashMap<String, HistoryData>>()
+                .map(|launch| History { launch, ..History::default() })
+        }),
+    };
+    parsed
+        .map(|mut history| {
+            history.version = HISTORY_VERSION;
+            history
+        })
+        .map_err(|err| err.to_string())
+}
+
+fn parse_history(path: &Path, history_str: &str) -> History {
+    parse_history_str(history_str).unwrap_or_else(|err| {
+        quarantine(path, &err);
+        History::default()
+    })
+}
+
+fn read_history(path: &Path) -> History {
+    match read_cache_file(path) {
+        Some(history_str) => parse_history(path, &history_str),
+        None => History::default(),
+    }
+}
+
+pub fn load_history(days: u32) -> History {
     match get_history_file(false) {
         Some(file) => {
-            let history_str = std::fs::read_to_string(file).expect("Cannot read history file");
-            let epoch = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards");
-            let cutoff = epoch.as_secs() - (days as u64) * 86400;
-            let mut history = toml::from_str(&history_str)
-                .unwrap_or_else(|err| {
-                    eprintln!("Cannot parse history file: {}", err);
-                    HashMap::new()
-                });
-            history.retain(|_, data : &mut HistoryData| {
-                    days == 0 || data.last_used >= cutoff
-            });
-            return history;
-        }
-        _ => HashMap::new(),
-    }
-}
-
-pub fn save_history(history: &HashMap<String, HistoryData>) {
-    let file = get_history_file(true).expect("Cannot create history file or cache directory");
-    let mut file = File::create(file).expect("Cannot open history file for writing");
-    let s = toml::to_string(history).unwrap();
-    file.write_all(s.as_bytes())
-        .expect("Cannot write to history file");
-}
-
-pub fn update_history(history: &mut HashMap<String, HistoryData>, id: &str) {
-    let epoch = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards");
-    let usage_count = history.get(&id.to_string()).map_or(0, |h| h.usage_count) + 1;
+            let _lock = FileLock::acquire(&file);
+            let mut history = read_history(&file);
+            if days > 0 && prune_history(&mut history, now_secs().saturating_sub((days as u64) * 86400)) {
+                // Written back right away, or the next save would merge the pruned entries back in
+                write_history_file(&file, &mut history);
+            }
+            history
+        }
+        _ => History::default(),
+    }
+}
+
+// Drops what hasn't been used since cutoff, returns whether anything went
+fn prune_history(history: &mut History, cutoff: u64) -> bool {
+    let mut pruned = false;
+    for kind in [HistoryKind::Launch, HistoryKind::Focus, HistoryKind::Command] {
+        let map = history.kind_mut(kind);
+        let len = map.len();
+        map.retain(|_, data| data.last_used >= cutoff);
+        pruned |= map.len() != len;
+    }
+    pruned
+}
+
+fn history_path(place: bool) -> Option<PathBuf> {
+    let path = get_history_file(place);
+    if path.is_none() {
+        log_warn!("Cannot create history file or cache directory");
+    }
+    path
+}
+
+// Merges with whatever is on disk, so concurrent instances don't drop each other's launches.
+// Removals (pruning, closed windows, forget) are written through edit_history first, so the merge can't undo them
+pub fn save_history(history: &mut History) {
+    if let Some(file) = history_path(true) {
+        let _lock = FileLock::acquire(&file);
+        // Pins are only changed through edit_history, so the file has the current ones
+        let stored = read_history(&file);
+        let pinned = stored.pinned.clone();
+        history.merge(stored);
+        history.pinned = pinned;
+        write_history_file(&file, history);
+    }
+}
+
+// Read-modify-write under a single lock, for when entries are removed on purpose
+pub fn edit_history<F: FnOnce(&mut History)>(f: F) {
+    if let Some(file) = history_path(true) {
+        let _lock = FileLock::acquire(&file);
+        let mut history = read_history(&file);
+        f(&mut history);
+        write_history_file(&file, &mut history);
+    }
+}
+
+fn write_history_file(file: &Path, history: &mut History) {
+    history.version = HISTORY_VERSION;
+    match toml::to_string(history) {
+        Ok(s) => {
+            if let Err(err) = write_atomic(file, &s) {
+                log_warn!("Cannot write history file: {}", err);
+            }
+        }
+        Err(err) => log_warn!("Cannot serialize history: {}", err),
+    }
+}
+
+pub fn update_history(history: &mut HashMap<String, HistoryData>, id: &str, half_life_days: u32) {
+    let now = now_secs();
+    let previous = history.get(&id.to_string()).copied().unwrap_or_default();
 
     history.insert(
         id.to_string(),
         HistoryData {
-            last_used: epoch.as_secs(),
-            usage_count,
+            last_used: now,
+            usage_count: previous.usage_count + 1,
+            frecency: previous.frecency_at(now, half_life_days) + 1.0,
         },
     );
-}
\ No newline at end of file
+}
+
+// query -> entry id -> how often (and how recently) that entry was picked for that query
+pub type QueryHistory = HashMap<String, HashMap<String, HistoryData>>;
+
+fn normalize_query(query: &str) -> String {
+    query.trim().to_lowercase()
+}
+
+fn read_query_history(file: &Path) -> QueryHistory {
+    match read_cache_file(file).map(|s| toml::from_str(&s)) {
+        Some(Ok(history)) => history,
+        Some(Err(err)) => {
+            quarantine(file, &err);
+            HashMap::new()
+        }
+        None => HashMap::new(),
+    }
+}
+
+pub fn load_query_history(days: u32) -> QueryHistory {
+    let file = match get_cache_file(QUERY_HISTORY_FILE, false) {
+        Some(file) => file,
+        _ => return HashMap::new(),
+    };
+    let _lock = FileLock::acquire(&file);
+    let mut history = read_query_history(&file);
+    // Same aging as load_history, written back so the next save doesn't merge it back in
+    if days > 0 && prune_query_history(&mut history, now_secs().saturating_sub((days as u64) * 86400)) {
+        write_query_history_file(&file, &history);
+    }
+    history
+}
+
+fn prune_query_history(history: &mut QueryHistory, cutoff: u64) -> bool {
+    let len: usize = history.values().map(HashMap::len).sum();
+    for choices in history.values_mut() {
+        choices.retain(|_, data| data.last_used >= cutoff);
+    }
+    history.retain(|_, choices| !choices.is_empty());
+    history.values().map(HashMap::len).sum::<usize>() != len
+}
+
+fn query_history_path() -> Option<PathBuf> {
+    let path = get_cache_file(QUERY_HISTORY_FILE, true);
+    if path.is_none() {
+        log_warn!("Cannot create query history file or cache directory");
+    }
+    path
+}
+
+// Merges like save_history, for every query and entry the most recent pick wins
+pub fn save_query_history(history: &mut QueryHistory) {
+    if let Some(file) = query_history_path() {
+        let _lock = FileLock::acquire(&file);
+        merge_query_history(history, read_query_history(&file));
+        write_query_history_file(&file, history);
+    }
+}
+
+// Same as edit_history, for forgetting entries
+pub fn edit_query_history<F: FnOnce(&mut QueryHistory)>(f: F) {
+    if let Some(file) = query_history_path() {
+        let _lock = FileLock::acquire(&file);
+        let mut history = read_query_history(&file);
+        f(&mut history);
+        write_query_history_file(&file, &history);
+    }
+}
+
+fn merge_query_history(history: &mut QueryHistory, other: QueryHistory) {
+    for (query, choices) in other {
+        let map = history.entry(query).or_insert_with(HashMap::new);
+        for (id, data) in choices {
+            match map.get(&id) {
+                Some(current) if current.last_used >= data.last_used => {}
+                _ => {
+                    map.insert(id, data);
+                }
+            }
+        }
+    }
+}
+
+fn write_query_history_file(file: &Path, history: &QueryHistory) {
+    match toml::to_string(history) {
+        Ok(s) => {
+            if let Err(err) = write_atomic(file, &s) {
+                log_warn!("Cannot write query history file: {}", err);
+            }
+        }
+        Err(err) => log_warn!("Cannot serialize query history: {}", err),
+    }
+}
+
+pub fn forget_query_choices(history: &mut QueryHistory, id: &str) {
+    for choices in history.values_mut() {
+        choices.remove(id);
+    }
+    history.retain(|_, choices| !choices.is_empty());
+}
+
+pub fn update_query_history(history: &mut QueryHistory, query: &str, id: &str, half_life_days: u32) {
+    let query = normalize_query(query);
+    if query.is_empty() {
+        return;
+    }
+    update_history(history.entry(query).or_insert_with(HashMap::new), id, half_life_days);
+}
+
+// Every entry picked for the typed query, or for a shorter query it extends, gets a boost
+pub fn query_boosts(history: &QueryHistory, query: &str, now: u64, half_life_days: u32, weight: f64) -> HashMap<String, i64> {
+    let query = normalize_query(query);
+    let mut boosts: HashMap<String, f64> = HashMap::new();
+    if query.is_empty() {
+        return HashMap::new();
+    }
+    for (prefix, choices) in history {
+        if query.starts_with(prefix.as_str()) {
+            for (id, data) in choices {
+                *boosts.entry(id.clone()).or_insert(0.0) += data.frecency_at(now, half_life_days);
+            }
+        }
+    }
+    boosts
+        .into_iter()
+        .map(|(id, score)| (id, (weight * score.ln_1p()).round() as i64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const DAY: u64 = 86400;
+
+    fn used(days_ago: u64, count: u32) -> HistoryData {
+        HistoryData { last_used: NOW - days_ago * DAY, usage_count: count, frecency: count as f64 }
+    }
+
+    // What the launcher sorts on: the matcher score plus the rounded boost
+    fn ranked(score: i64, data: &HistoryData, weight: f64) -> i64 {
+        score + frecency_boost(data, NOW, 14, weight).round() as i64
+    }
+
+    #[test]
+    fn frecency_halves_every_half_life() {
+        let data = used(0, 8);
+        assert_eq!(data.frecency_at(NOW, 14), 8.0);
+        assert!((data.frecency_at(NOW + 14 * DAY, 14) - 4.0).abs() < 1e-9);
+        assert!((data.frecency_at(NOW + 28 * DAY, 14) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frecency_without_half_life_does_not_decay() {
+        assert_eq!(used(365, 8).frecency_at(NOW, 0), 8.0);
+    }
+
+    #[test]
+    fn frecency_of_old_entries_starts_from_usage_count() {
+        let data = HistoryData { last_used: NOW, usage_count: 5, frecency: 0.0 };
+        assert_eq!(data.frecency_at(NOW, 14), 5.0);
+    }
+
+    #[test]
+    fn recent_favourite_beats_old_heavy_user() {
+        let favourite = used(1, 10);
+        let heavy = used(90, 200);
+        assert!(frecency_boost(&favourite, NOW, 14, 10.0) > frecency_boost(&heavy, NOW, 14, 10.0));
+        assert!(ranked(50, &favourite, 10.0) > ranked(50, &heavy, 10.0));
+    }
+
+    #[test]
+    fn weight_blends_frecency_with_match_score() {
+        let favourite = used(1, 10);
+        let never = HistoryData::default();
+        // Without weight the better match wins, with the default weight the favourite does
+        assert_eq!(frecency_boost(&favourite, NOW, 14, 0.0), 0.0);
+        assert!(ranked(60, &never, 0.0) > ranked(50, &favourite, 0.0));
+        assert!(ranked(60, &never, 10.0) < ranked(50, &favourite, 10.0));
+        assert!(frecency_boost(&favourite, NOW, 14, 20.0) > frecency_boost(&favourite, NOW, 14, 10.0));
+    }
+
+    #[test]
+    fn query_history_merge_keeps_both_instances_picks() {
+        let choices = |id: &str, data: HistoryData| vec![(id.to_string(), data)].into_iter().collect::<HashMap<_, _>>();
+        let mut ours: QueryHistory = vec![("fi".to_string(), choices("firefox", used(0, 3)))].into_iter().collect();
+        let theirs: QueryHistory = vec![
+            ("fi".to_string(), choices("firefox", used(5, 9))),
+            ("te".to_string(), choices("terminal", used(1, 2))),
+        ]
+        .into_iter()
+        .collect();
+        merge_query_history(&mut ours, theirs);
+        assert_eq!(ours["fi"]["firefox"].usage_count, 3);
+        assert_eq!(ours["te"]["terminal"].usage_count, 2);
+    }
+
+    #[test]
+    fn sirula_flat_map_becomes_launch_history() {
+        let sirula = r#"
+            ["firefox.desktop"]
+            last_used = 1700000000
+            usage_count = 12
+
+            ["org.gnome.Nautilus.desktop"]
+            last_used = 1690000000
+            usage_count = 3
+        "#;
+        let history = parse_history_str(sirula).unwrap();
+        assert_eq!(history.version, HISTORY_VERSION);
+        assert_eq!(history.launch.len(), 2);
+        assert_eq!(history.launch["firefox.desktop"].usage_count, 12);
+        assert_eq!(history.launch["org.gnome.Nautilus.desktop"].last_used, 1_690_000_000);
+        assert!(history.focus.is_empty() && history.command.is_empty() && history.pinned.is_empty());
+    }
+
+    #[test]
+    fn unversioned_split_format_keeps_its_kinds() {
+        let split = r#"
+            [launch."firefox.desktop"]
+            last_used = 1700000000
+            usage_count = 12
+
+            [focus."42"]
+            last_used = 1700000100
+            usage_count = 2
+
+            [command."htop"]
+            last_used = 1700000200
+            usage_count = 1
+        "#;
+        let history = parse_history_str(split).unwrap();
+        assert_eq!(history.version, HISTORY_VERSION);
+        assert_eq!(history.launch["firefox.desktop"].usage_count, 12);
+        assert_eq!(history.focus["42"].last_used, 1_700_000_100);
+        assert_eq!(history.command["htop"].usage_count, 1);
+        // Not mistaken for a flat map with "launch", "focus" and "command" entries
+        assert!(!history.launch.contains_key("launch"));
+    }
+}
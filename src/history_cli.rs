@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+
+use crate::consts::HISTORY_FILE;
+use crate::error::Error;
+use crate::history::*;
+
+const USAGE: &str = "Usage: skoll history list [launch|focus|command]
+       skoll history forget <id>
+       skoll history reset
+       skoll history export [file]
+       skoll history import <file|sirula>";
+
+// Returns the exit code when the arguments are a history command, None to start the launcher
+pub fn run_history_command(args: &[String]) -> Option<i32> {
+    if args.get(1).map(String::as_str) != Some("history") {
+        return None;
+    }
+    let args: Vec<&str> = args[2..].iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["list"] => list(None),
+        ["list", kind] => parse_kind(kind).and_then(|kind| list(Some(kind))),
+        ["forget", id] => forget(id),
+        ["reset"] => reset(),
+        ["export"] => export(None),
+        ["export", file] => export(Some(file)),
+        ["import", source] => import(source),
+        _ => {
+            eprintln!("{}", USAGE);
+            return Some(1);
+        }
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    })
+}
+
+fn parse_kind(kind: &str) -> Result<HistoryKind, Error> {
+    match kind {
+        "launch" => Ok(HistoryKind::Launch),
+        "focus" => Ok(HistoryKind::Focus),
+        "command" => Ok(HistoryKind::Command),
+        other => Err(Error::parse(format!("history kind {}", other), "expected launch, focus or command")),
+    }
+}
+
+fn kind_name(kind: HistoryKind) -> &'static str {
+    match kind {
+        HistoryKind::Launch => "launch",
+        HistoryKind::Focus => "focus",
+        HistoryKind::Command => "command",
+    }
+}
+
+fn list(only: Option<HistoryKind>) -> Result<(), Error> {
+    let history = load_history(0);
+    let now = now_secs();
+    let kinds = [HistoryKind::Launch, HistoryKind::Focus, HistoryKind::Command];
+    for kind in kinds.iter().copied().filter(|k| only.map_or(true, |only| only == *k)) {
+        let mut entries: Vec<(&String, &HistoryData)> = history.kind(kind).iter().collect();
+        // half life 0 means no decay: the stored score as is
+        entries.sort_by(|a, b| b.1.frecency_at(now, 0).total_cmp(&a.1.frecency_at(now, 0)));
+        for (id, data) in entries {
+            let days = now.saturating_sub(data.last_used) / 86400;
+            println!("{:<8} {:>5} uses  {:>4}d ago  {:>7.2}  {}", kind_name(kind), data.usage_count, days, data.frecency, id);
+        }
+    }
+    Ok(())
+}
+
+fn forget(id: &str) -> Result<(), Error> {
+    let mut found = false;
+    edit_history(|history| found = history.forget(id));
+    edit_query_history(|query_history| forget_query_choices(query_history, id));
+    if found {
+        Ok(())
+    } else {
+        Err(Error::command("history forget", format!("{} is not in the history", id)))
+    }
+}
+
+fn reset() -> Result<(), Error> {
+    edit_history(|history| *history = History::default());
+    edit_query_history(|query_history| query_history.clear());
+    Ok(())
+}
+
+fn export(file: Option<&str>) -> Result<(), Error> {
+    let mut history = load_history(0);
+    history.version = HISTORY_VERSION;
+    let s = toml::to_string(&history).map_err(|err| Error::command("history export", err))?;
+    match file {
+        Some(file) => std::fs::write(file, s).map_err(|err| Error::io(format!("Cannot write {}", file), err)),
+        None => {
+            print!("{}", s);
+            Ok(())
+        }
+    }
+}
+
+fn sirula_history_file() -> Result<PathBuf, Error> {
+    xdg::BaseDirectories::with_prefix("sirula")
+        .ok()
+        .and_then(|xdg| xdg.find_cache_file(HISTORY_FILE))
+        .ok_or_else(|| Error::command("history import", "no sirula history found"))
+}
+
+fn import(source: &str) -> Result<(), Error> {
+    let file = if source == "sirula" { sirula_history_file()? } else { PathBuf::from(source) };
+    let history_str = std::fs::read_to_string(&file)
+        .map_err(|err| Error::io(format!("Cannot read {}", file.display()), err))?;
+    let imported = parse_history_str(&history_str)
+        .map_err(|err| Error::parse(file.display().to_string(), err))?;
+    let count = imported.launch.len() + imported.focus.len() + imported.command.len();
+    edit_history(|history| history.merge(imported));
+    println!("Imported {} entries from {}", count, file.display());
+    Ok(())
+}
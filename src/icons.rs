@@ -0,0 +1,249 @@
+use gdk_pixbuf::Pixbuf;
+use gio::prelude::*;
+use gio::{AppInfo, DesktopAppInfo, Icon};
+use gtk::prelude::*;
+use gtk::{IconLookupFlags, IconTheme, Image};
+use serde_derive::{Deserialize, Serialize};
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::consts::ICON_CACHE_FILE;
+use crate::history::now_secs;
+use crate::profile;
+use crate::util::{get_xdg_dirs, write_atomic};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InfoIcon {
+    Theme(String),
+    File(PathBuf),
+    Glyph(String),
+}
+
+pub const IMAGE_EXTENSIONS: [&str; 5] = [".svg", ".svgz", ".png", ".jpg", ".xpm"];
+
+impl InfoIcon {
+    // "theme:weather-clear" is a theme icon, paths are images, anything else is text (Nerd Font glyphs)
+    pub fn parse(icon: &str) -> InfoIcon {
+        if let Some(name) = icon.strip_prefix("theme:") {
+            InfoIcon::Theme(name.into())
+        } else if let Some(rest) = icon.strip_prefix("~/") {
+            InfoIcon::File(std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(rest))
+        } else if icon.starts_with('/')
+            || icon.starts_with("./")
+            || IMAGE_EXTENSIONS.iter().any(|ext| icon.to_lowercase().ends_with(ext))
+        {
+            InfoIcon::File(icon.into())
+        } else {
+            InfoIcon::Glyph(icon.into())
+        }
+    }
+}
+
+thread_local! {
+    // Failures are cached too, so a missing file doesn't hit the disk (and the log) on every refresh
+    static PIXBUF_CACHE: RefCell<HashMap<(PathBuf, i32), Option<Pixbuf>>> = RefCell::new(HashMap::new());
+}
+
+pub fn load_pixbuf(path: &PathBuf, size: i32) -> Option<Pixbuf> {
+    PIXBUF_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry((path.clone(), size))
+            .or_insert_with(|| match Pixbuf::from_file_at_size(path, size, size) {
+                Ok(pixbuf) => Some(pixbuf),
+                Err(err) => {
+                    log_warn!("Cannot load icon {}: {}", path.display(), err);
+                    None
+                }
+            })
+            .clone()
+    })
+}
+
+// Launcher rows: the theme lookup of every app icon is slow, so it happens on idle after the
+// window is up and what it finds is kept on disk for the next start
+#[derive(Default, Deserialize, Serialize)]
+struct IconCacheFile {
+    theme: String,
+    // "<app id or icon name>|<size>" -> resolved file, None when the theme has nothing
+    icons: HashMap<String, CachedIcon>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct CachedIcon {
+    path: Option<PathBuf>,
+    // mtime of the desktop file the icon comes from
+    stamp: u64,
+    // When the theme was asked, a missing icon may get installed later
+    #[serde(default)]
+    checked: u64,
+}
+
+// How long "the theme has nothing" is believed
+const MISSING_ICON_TTL: u64 = 24 * 3600;
+
+impl CachedIcon {
+    fn valid(&self, stamp: u64, now: u64) -> bool {
+        self.stamp == stamp && (self.path.is_some() || now.saturating_sub(self.checked) < MISSING_ICON_TTL)
+    }
+}
+
+struct PendingIcon {
+    image: Image,
+    icon: Icon,
+    key: String,
+    stamp: u64,
+    size: i32,
+}
+
+thread_local! {
+    static ICON_CACHE: RefCell<Option<IconCacheFile>> = RefCell::new(None);
+    static PENDING_ICONS: RefCell<VecDeque<PendingIcon>> = RefCell::new(VecDeque::new());
+    static CACHE_CHANGED: Cell<bool> = Cell::new(false);
+}
+
+// Icons resolved per idle callback, small enough to keep typing smooth
+const ICONS_PER_IDLE: usize = 8;
+// Shown while the real icon is pending
+const PLACEHOLDER_ICON: &str = "application-x-executable";
+
+fn current_theme() -> String {
+    gtk::Settings::default()
+        .and_then(|settings| settings.gtk_icon_theme_name())
+        .map(|name| name.to_string())
+        .unwrap_or_default()
+}
+
+fn with_icon_cache<T, F: FnOnce(&mut IconCacheFile) -> T>(f: F) -> T {
+    ICON_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let cache = cache.get_or_insert_with(|| {
+            let theme = current_theme();
+            let stored: Option<IconCacheFile> = get_xdg_dirs()
+                .find_cache_file(ICON_CACHE_FILE)
+                .and_then(|file| std::fs::read_to_string(file).ok())
+                .and_then(|s| serde_json::from_str(&s).ok());
+            match stored {
+                // Another theme means other files
+                Some(stored) if stored.theme == theme => stored,
+                _ => IconCacheFile { theme, icons: HashMap::new() },
+            }
+        });
+        f(cache)
+    })
+}
+
+fn save_icon_cache() {
+    let s = with_icon_cache(|cache| serde_json::to_string(cache));
+    match (get_xdg_dirs().place_cache_file(ICON_CACHE_FILE), s) {
+        (Ok(file), Ok(s)) => {
+            // Another launcher may be reading it or writing it too
+            if let Err(err) = write_atomic(&file, &s) {
+                log_warn!("Cannot write icon cache: {}", err);
+            }
+        }
+        _ => log_warn!("Cannot create icon cache"),
+    }
+}
+
+pub fn icon_key(app: Option<&AppInfo>, icon: &Icon, size: i32) -> String {
+    let name = app
+        .and_then(|app| app.id())
+        .or_else(|| IconExt::to_string(icon))
+        .map(|name| name.to_string())
+        .unwrap_or_default();
+    format!("{}|{}", name, size)
+}
+
+// Editing or reinstalling the desktop file may change the icon
+pub fn desktop_stamp(app: Option<&AppInfo>) -> u64 {
+    app.and_then(|app| app.downcast_ref::<DesktopAppInfo>())
+        .and_then(|info| info.filename())
+        .and_then(|file| std::fs::metadata(file).ok())
+        .and_then(|meta| meta.modified().ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Rows are recycled, so an image may still be waiting for the icon of the entry it showed before
+pub fn clear_app_icon(image: &Image) {
+    PENDING_ICONS.with(|queue| queue.borrow_mut().retain(|pending| pending.image != *image));
+    image.clear();
+}
+
+// Even a cached icon means decoding an image, so every row shows the placeholder until an idle pass gets to it
+pub fn set_app_icon(image: &Image, icon: &Icon, key: String, stamp: u64, size: i32) {
+    clear_app_icon(image);
+    image.set_from_icon_name(Some(PLACEHOLDER_ICON), gtk::IconSize::Menu);
+    queue_icon(PendingIcon { image: image.clone(), icon: icon.clone(), key, stamp, size });
+}
+
+fn queue_icon(pending: PendingIcon) {
+    let start = PENDING_ICONS.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        queue.push_back(pending);
+        queue.len() == 1
+    });
+    if start {
+        glib::idle_add_local(resolve_pending_icons);
+    }
+}
+
+fn resolve_pending_icons() -> glib::Continue {
+    let _span = profile::span("icon_lookup");
+    let icon_theme = match IconTheme::default() {
+        Some(icon_theme) => icon_theme,
+        None => {
+            // Nothing will restart the queue, leave the placeholders alone
+            log_warn!("No icon theme, application icons are not resolved");
+            PENDING_ICONS.with(|queue| queue.borrow_mut().clear());
+            return glib::Continue(false);
+        }
+    };
+    let now = now_secs();
+    for _ in 0..ICONS_PER_IDLE {
+        let pending = match PENDING_ICONS.with(|queue| queue.borrow_mut().pop_front()) {
+            Some(pending) => pending,
+            None => break,
+        };
+        let cached = with_icon_cache(|cache| cache.icons.get(&pending.key).filter(|c| c.valid(pending.stamp, now)).cloned());
+        match cached {
+            Some(CachedIcon { path: Some(path), .. }) => {
+                if let Some(pixbuf) = load_pixbuf(&path, pending.size) {
+                    pending.image.set_from_pixbuf(Some(&pixbuf));
+                    continue;
+                }
+            }
+            // Don't set the icon if it'd give us an ugly fallback icon
+            Some(CachedIcon { path: None, .. }) => {
+                pending.image.clear();
+                continue;
+            }
+            None => {}
+        }
+        let path = icon_theme
+            .lookup_by_gicon(&pending.icon, pending.size, IconLookupFlags::FORCE_SIZE)
+            .and_then(|info| info.filename());
+        if path.is_some() {
+            pending.image.set_from_gicon(&pending.icon, gtk::IconSize::Menu);
+        } else {
+            pending.image.clear();
+        }
+        let cached = CachedIcon { path, stamp: pending.stamp, checked: now };
+        with_icon_cache(|cache| cache.icons.insert(pending.key, cached));
+        CACHE_CHANGED.with(|changed| changed.set(true));
+    }
+    if PENDING_ICONS.with(|queue| queue.borrow().is_empty()) {
+        // A warm start found everything in the cache, no need to write it again
+        if CACHE_CHANGED.with(|changed| changed.replace(false)) {
+            save_icon_cache();
+        }
+        glib::Continue(false)
+    } else {
+        glib::Continue(true)
+    }
+}
@@ -1,119 +1,265 @@
-use gdk_pixbuf::Pixbuf;
 use gtk::builders::BoxBuilder;
 use gtk::prelude::*;
-use gtk::{glib, Align, Grid, Image, Label};
+use gtk::{gdk, glib, Adjustment, Align, CssProvider, EventBox, Grid, IconTheme, Image, Label, Orientation, Scale};
 
 use std::collections::HashMap;
 
-pub trait InfoView {
-    fn new(info_keys: &[(String, String, String, String)]) -> Self;
-    fn widget(&self) -> &gtk::Widget;
-    fn update_value(&self, id: &str, new_value: &str) -> &Self;
-    fn update_path(&self, id: &str, new_icon_path: &str) -> &Self;
-    fn update_color(&self, id: &str, color_css: &str) -> &Self;
-    fn update_icon(&self, id: &str, icon_text: &str) -> &Self;
+use crate::icons::{load_pixbuf, InfoIcon};
+use crate::sparkline::Sparkline;
+
+#[derive(Clone, Default)]
+pub struct InfoItem {
+    pub id: String,
+    pub label: String,
+    pub icon: String,
+    pub value: String,
+    pub progress: Option<f64>,
+    pub color: Option<String>,
+    pub state: Option<String>,
 }
 
-static ICONSIZE: i32 = 16;
+impl InfoItem {
+    pub fn new(id: &str, label: &str, icon: &str) -> Self {
+        InfoItem {
+            id: id.into(),
+            label: label.into(),
+            icon: icon.into(),
+            ..Default::default()
+        }
+    }
+}
 
-pub struct InfoGrid {
-    container: gtk::Widget,
-    rows: HashMap<String, (Image, Label, Label, Label)>,
+static SPARKLINE_WIDTH: i32 = 60;
+
+// All the widgets an item can use, every renderer decides which ones to show and where
+pub struct InfoCell {
+    parts: Vec<gtk::Widget>,
+    icon: Image,
+    icon_label: Label,
+    icon_size: i32,
+    label: Label,
+    value: Label,
+    events: EventBox,
+    sparkline: Sparkline,
+    progress: Option<Scale>,
+    progress_css: CssProvider,
+    colored: Vec<Label>,
 }
 
-impl InfoView for InfoGrid {
-    fn new(info_keys: &[(String, String, String, String)]) -> Self {
-        // info_keys: Vec<(id, label, icon_path)>
-        let grid = Grid::new();
-        grid.set_column_spacing(10);
-        grid.set_row_spacing(4);
-        grid.set_halign(Align::End);
+impl InfoCell {
+    fn new(item: &InfoItem, icon_size: i32) -> Self {
+        let icon = Image::new();
+        icon.set_pixel_size(0);
+
+        // The real icon is set by apply(), once we know if it's a glyph or an image
+        let icon_label = Label::new(None);
+        icon_label.set_halign(Align::Start);
+        icon_label.style_context().add_class("grid-icon");
+
+        let label = Label::new(Some(&item.label));
+        label.set_halign(Align::Start);
+
+        let value = Label::new(Some(if item.value.is_empty() { "---" } else { &item.value }));
+        value.set_halign(Align::Start);
+        value.set_xalign(1.0);
+        value.style_context().add_class("value");
+
+        // Hidden until the first samples arrive, so metrics without history take no space
+        let sparkline = Sparkline::new(SPARKLINE_WIDTH, icon_size);
+        sparkline.area.set_no_show_all(true);
+
+        InfoCell {
+            parts: Vec::new(),
+            icon,
+            icon_label,
+            icon_size,
+            label,
+            value,
+            events: EventBox::new(),
+            sparkline,
+            progress: None,
+            progress_css: CssProvider::new(),
+            colored: Vec::new(),
+        }
+    }
 
-        let mut rows = HashMap::new();
+    fn with_progress(mut self, orientation: Orientation) -> Self {
+        let adjustment = Adjustment::new(0.0, 0.0, 100.0, 1.0, 10.0, 0.0);
+        let scale = Scale::new(orientation, Some(&adjustment));
+        scale.set_draw_value(false);
+        scale.set_sensitive(false);
+        if orientation == Orientation::Vertical {
+            scale.set_inverted(true);
+            scale.set_vexpand(true);
+        }
+        scale.style_context().add_provider(&self.progress_css, gtk::STYLE_PROVIDER_PRIORITY_USER);
+        self.progress = Some(scale);
+        self
+    }
 
-        for (i, (id, label_text, icon_text, icon_path)) in info_keys.iter().enumerate() {
-            let icon: Image;
-            if !icon_path.is_empty() {
-                icon = Image::from_file(icon_path);
-                let pixbuf = Pixbuf::from_file_at_size(icon_path, ICONSIZE, ICONSIZE);
-                if let Ok(pixbuf_) = pixbuf {
-                    icon.set_from_pixbuf(Some(&pixbuf_));
-                }
-            } else {
-                icon = Image::new();
-            }
-            icon.set_pixel_size(ICONSIZE);
-            
+    fn apply(&self, item: &InfoItem) {
+        self.set_icon(&item.icon);
+        if !item.value.is_empty() {
+            self.set_value(&item.value);
+        }
+        if let Some(progress) = item.progress {
+            self.set_progress(progress);
+        }
+        if let Some(color) = &item.color {
+            self.set_color(color);
+        }
+        if let Some(state) = &item.state {
+            self.set_class(state);
+        }
+    }
 
-            let icon_label = Label::new(Some(icon_text));
-            icon_label.set_halign(Align::Start);
-            icon_label.style_context().add_class("grid-icon");
+    fn set_value(&self, text: &str) {
+        self.value.set_text(text);
+    }
 
-            let label = Label::new(Some(label_text));
-            label.set_halign(Align::Start);
+    fn set_label(&self, text: &str) {
+        self.label.set_text(text);
+    }
 
-            let value = Label::new(Some("…"));
-            value.set_halign(Align::Start);
-            value.set_xalign(1.0);
+    fn show_glyph(&self, text: &str) {
+        self.icon.clear();
+        self.icon.set_pixel_size(0);
+        self.icon_label.set_text(text);
+        self.icon_label.show();
+    }
 
-            grid.attach(&icon, 0, i as i32, 1, 1);
-            grid.attach(&icon_label, 0, i as i32, 1, 1);
-            grid.attach(&label, 1, i as i32, 1, 1);
-            grid.attach(&value, 2, i as i32, 1, 1);
+    fn show_image(&self) {
+        self.icon.set_pixel_size(self.icon_size);
+        self.icon_label.hide();
+    }
 
-            rows.insert(id.clone(), (icon, icon_label, label, value));
+    // When an image can't be loaded the previous icon stays where it is
+    fn set_icon_source(&self, icon: InfoIcon) {
+        match icon {
+            InfoIcon::Glyph(text) => self.show_glyph(&text),
+            InfoIcon::Theme(name) => {
+                if IconTheme::default().map_or(false, |theme| theme.has_icon(&name)) {
+                    self.icon.set_from_icon_name(Some(&name), gtk::IconSize::Menu);
+                    self.show_image();
+                } else {
+                    log_warn!("Icon {} not found in the current theme", name);
+                }
+            }
+            InfoIcon::File(path) => {
+                if let Some(pixbuf) = load_pixbuf(&path, self.icon_size) {
+                    self.icon.set_from_pixbuf(Some(&pixbuf));
+                    self.show_image();
+                }
+            }
         }
+    }
 
-        Self {
-            container: grid.upcast(),
-            rows,
+    fn set_path(&self, path: &str) {
+        self.set_icon_source(InfoIcon::File(path.into()));
+    }
+
+    fn set_color(&self, color_css: &str) {
+        for label in &self.colored {
+            label.set_markup(&format!(r#"<span foreground="{}">{}</span>"#, color_css, glib::markup_escape_text(&label.text())));
+        }
+        if self.progress.is_some() {
+            let css = format!("scale highlight {{ background-color: {}; }} scale slider {{ all: unset; }}", color_css);
+            if let Err(err) = self.progress_css.load_from_data(css.as_bytes()) {
+                log_warn!("Invalid color {}: {}", color_css, err);
+            }
         }
     }
 
-    fn widget(&self) -> &gtk::Widget {
-        &self.container.upcast_ref()
+    fn set_icon(&self, icon: &str) {
+        self.set_icon_source(InfoIcon::parse(icon));
     }
 
-    fn update_value(&self, id: &str, new_value: &str) -> &Self {
-        if let Some((_, _, _, value_label)) = self.rows.get(id) {
-            value_label.set_text(new_value);
+    fn set_progress(&self, progress: f64) {
+        if let Some(scale) = &self.progress {
+            scale.set_value(progress);
         }
-        &self
     }
 
-    fn update_path(&self, id: &str, new_icon_path: &str) -> &Self {
-        if let Some((icon, _, _, _)) = self.rows.get(id) {
-            // icon.set_from_file(Some(new_icon_path));
-            let pixbuf = Pixbuf::from_file_at_size(new_icon_path, ICONSIZE, ICONSIZE).unwrap();
-            icon.set_from_pixbuf(Some(&pixbuf));
+    // Same class names used for the ok/warn/crit states everywhere
+    fn set_class(&self, class: &str) {
+        for label in &self.colored {
+            let context = label.style_context();
+            for cls in ["ok", "warn", "crit"] {
+                context.remove_class(cls);
+            }
+            context.add_class(class);
         }
-        &self
     }
 
-    fn update_color(&self, id: &str, color_css: &str) -> &Self {
-        if let Some((_, _, _, value_label)) = self.rows.get(id) {
-            value_label.set_markup(&format!(r#"<span foreground="{}">{}</span>"#, color_css, glib::markup_escape_text(&value_label.text())));
-        }
-        &self
+    fn set_points(&self, points: Vec<(f64, String)>) {
+        self.sparkline.area.show();
+        self.sparkline.set_points(points);
     }
 
-    fn update_icon(&self, id: &str, icon_text: &str) -> &Self {
-        if let Some((_, icon_label, _, _)) = self.rows.get(id) {
-            icon_label.set_text(icon_text);
-        }
-        &self
+    fn make_interactive(&self) {
+        self.events.add_events(gdk::EventMask::SCROLL_MASK | gdk::EventMask::SMOOTH_SCROLL_MASK | gdk::EventMask::BUTTON_PRESS_MASK);
+        self.events.style_context().add_class("interactive");
+    }
+
+    // Scroll up/right means +1, down/left means -1
+    fn connect_scroll<F: Fn(i32) + 'static>(&self, callback: F) {
+        self.make_interactive();
+        self.events.connect_scroll_event(move |_, event| {
+            let step = match event.direction() {
+                gdk::ScrollDirection::Up | gdk::ScrollDirection::Right => 1,
+                gdk::ScrollDirection::Down | gdk::ScrollDirection::Left => -1,
+                _ => {
+                    let (dx, dy) = event.delta();
+                    if dy < 0.0 || dx > 0.0 { 1 } else if dy > 0.0 || dx < 0.0 { -1 } else { 0 }
+                }
+            };
+            if step != 0 { callback(step) };
+            Inhibit(true)
+        });
+    }
+
+    fn connect_click<F: Fn() + 'static>(&self, callback: F) {
+        self.make_interactive();
+        self.events.connect_button_press_event(move |_, event| {
+            if event.button() == 1 {
+                callback();
+            }
+            Inhibit(true)
+        });
     }
 }
 
+fn island(orientation: Orientation) -> gtk::Box {
+    let innerbox = BoxBuilder::new()
+        .name("inner_box")
+        .orientation(orientation)
+        .vexpand(false)
+        .hexpand(false)
+        .halign(gtk::Align::Center)
+        .valign(gtk::Align::Start)
+        .build();
+    innerbox.style_context().add_class("island");
+    innerbox
+}
+
+pub trait InfoRenderer {
+    fn container(&self) -> &gtk::Container;
+    fn build_cell(&self, item: &InfoItem) -> InfoCell;
+    // Grids need to know the position, boxes just append
+    fn attach(&self, cell: &InfoCell, _index: usize) {
+        for part in &cell.parts {
+            self.container().add(part);
+        }
+    }
+}
 
-pub struct InfoBar {
-    container: gtk::Widget,
-    rows: HashMap<String, (Image, Label, Label)>,
+pub struct BarRenderer {
+    container: gtk::Container,
+    icon_size: i32,
 }
 
-impl InfoView for InfoBar {
-    fn new(info_keys: &[(String, String, String, String)]) -> Self {
-        // info_keys: Vec<(id, label, icon_path)>
+impl BarRenderer {
+    pub fn new(icon_size: i32) -> Self {
         let inforow = BoxBuilder::new()
             .name("info_bar")
             .orientation(gtk::Orientation::Horizontal)
@@ -122,94 +268,296 @@ impl InfoView for InfoBar {
             .halign(gtk::Align::Center)
             .valign(gtk::Align::Fill)
             .build();
+        Self { container: inforow.upcast(), icon_size }
+    }
+}
 
-        let mut rows = HashMap::new();
+impl InfoRenderer for BarRenderer {
+    fn container(&self) -> &gtk::Container {
+        &self.container
+    }
 
-        for (i, (id, _label_text, icon_text, icon_path)) in info_keys.iter().enumerate() {
-            let icon: Image;
-            if !icon_path.is_empty() {
-                icon = Image::from_file(icon_path);
-                let pixbuf = Pixbuf::from_file_at_size(icon_path, ICONSIZE, ICONSIZE);
-                if let Ok(pixbuf_) = pixbuf {
-                    icon.set_from_pixbuf(Some(&pixbuf_));
-                    icon.set_pixel_size(ICONSIZE);
-                }
-            } else {
-                icon = Image::new();
-                icon.set_pixel_size(0);
+    fn build_cell(&self, item: &InfoItem) -> InfoCell {
+        let mut cell = InfoCell::new(item, self.icon_size);
+        cell.icon_label.set_valign(Align::Start);
+
+        let innerbox = island(Orientation::Horizontal);
+        innerbox.add(&cell.icon);
+        innerbox.add(&cell.icon_label);
+        innerbox.add(&cell.value);
+        innerbox.add(&cell.sparkline.area);
+        cell.events.add(&innerbox);
+
+        cell.colored = vec![cell.icon_label.clone(), cell.value.clone()];
+        cell.parts = vec![cell.events.clone().upcast()];
+        cell
+    }
+}
+
+pub struct GridRenderer {
+    container: gtk::Container,
+    icon_size: i32,
+}
+
+impl GridRenderer {
+    pub fn new(icon_size: i32) -> Self {
+        let grid = Grid::new();
+        grid.set_column_spacing(10);
+        grid.set_row_spacing(4);
+        grid.set_halign(Align::End);
+        Self { container: grid.upcast(), icon_size }
+    }
+}
+
+impl InfoRenderer for GridRenderer {
+    fn container(&self) -> &gtk::Container {
+        &self.container
+    }
+
+    fn build_cell(&self, item: &InfoItem) -> InfoCell {
+        let mut cell = InfoCell::new(item, self.icon_size);
+        cell.events.add(&cell.value);
+        cell.colored = vec![cell.value.clone()];
+        cell.parts = vec![
+            cell.icon.clone().upcast(),
+            cell.icon_label.clone().upcast(),
+            cell.label.clone().upcast(),
+            cell.events.clone().upcast(),
+            cell.sparkline.area.clone().upcast(),
+        ];
+        cell
+    }
+
+    fn attach(&self, cell: &InfoCell, index: usize) {
+        if let Some(grid) = self.container.downcast_ref::<Grid>() {
+            let row = index as i32;
+            grid.insert_row(row);
+            // icon image and icon glyph share the first column
+            for (column, part) in [0, 0, 1, 2, 3].iter().zip(cell.parts.iter()) {
+                grid.attach(part, *column, row, 1, 1);
             }
-            
-            let innerbox = BoxBuilder::new()
-                .name("inner_box")
-                .orientation(gtk::Orientation::Horizontal)
-                .vexpand(false)
-                .hexpand(false)
-                .halign(gtk::Align::Center)
-                .valign(gtk::Align::Start)
-                .build();
-            innerbox.style_context().add_class("island");
+        }
+    }
+}
+
+pub struct GaugeRenderer {
+    container: gtk::Container,
+    icon_size: i32,
+}
+
+impl GaugeRenderer {
+    pub fn new(icon_size: i32) -> Self {
+        let gauges = BoxBuilder::new()
+            .name("info_gauges")
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(8)
+            .halign(gtk::Align::Center)
+            .build();
+        Self { container: gauges.upcast(), icon_size }
+    }
+}
+
+impl InfoRenderer for GaugeRenderer {
+    fn container(&self) -> &gtk::Container {
+        &self.container
+    }
+
+    fn build_cell(&self, item: &InfoItem) -> InfoCell {
+        let mut cell = InfoCell::new(item, self.icon_size).with_progress(Orientation::Vertical);
+        cell.icon_label.set_halign(Align::Center);
+        cell.value.set_halign(Align::Center);
+
+        let innerbox = island(Orientation::Vertical);
+        innerbox.style_context().add_class("gauge");
+        if let Some(scale) = &cell.progress {
+            innerbox.add(scale);
+        }
+        innerbox.add(&cell.icon_label);
+        innerbox.add(&cell.value);
+        cell.events.add(&innerbox);
+
+        cell.colored = vec![cell.icon_label.clone(), cell.value.clone()];
+        cell.parts = vec![cell.events.clone().upcast()];
+        cell
+    }
+}
+
+pub struct MultiRowRenderer {
+    container: gtk::Container,
+    icon_size: i32,
+}
+
+impl MultiRowRenderer {
+    pub fn new(icon_size: i32) -> Self {
+        let rows = BoxBuilder::new()
+            .name("info_rows")
+            .orientation(gtk::Orientation::Vertical)
+            .halign(gtk::Align::End)
+            .build();
+        Self { container: rows.upcast(), icon_size }
+    }
+}
+
+impl InfoRenderer for MultiRowRenderer {
+    fn container(&self) -> &gtk::Container {
+        &self.container
+    }
+
+    fn build_cell(&self, item: &InfoItem) -> InfoCell {
+        let mut cell = InfoCell::new(item, self.icon_size).with_progress(Orientation::Horizontal);
+        cell.icon_label.style_context().add_class("icon");
+
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        row.add(&cell.icon);
+        row.add(&cell.icon_label);
+        row.add(&cell.label);
+        if let Some(scale) = &cell.progress {
+            scale.set_hexpand(false);
+            scale.set_size_request(100, -1);
+            row.add(scale);
+        }
+        row.add(&cell.value);
+        cell.events.add(&row);
 
-            let icon_label = Label::new(Some(icon_text));
-            icon_label.set_valign(Align::Start);
-            icon_label.set_halign(Align::Start);
-            icon_label.style_context().add_class("grid-icon");
+        cell.colored = vec![cell.value.clone()];
+        cell.parts = vec![cell.events.clone().upcast()];
+        cell
+    }
+}
 
-            // let label = Label::new(Some(label_text));
-            // label.set_halign(Align::Start);
+pub fn renderer_by_name(name: &str, icon_size: i32) -> Box<dyn InfoRenderer> {
+    match name {
+        "grid" => Box::new(GridRenderer::new(icon_size)),
+        "gauge" => Box::new(GaugeRenderer::new(icon_size)),
+        "multirow" => Box::new(MultiRowRenderer::new(icon_size)),
+        "bar" => Box::new(BarRenderer::new(icon_size)),
+        other => {
+            log_warn!("Unknown info layout {}, using bar", other);
+            Box::new(BarRenderer::new(icon_size))
+        }
+    }
+}
 
-            let value = Label::new(Some("---"));
-            value.set_halign(Align::Start);
-            value.set_xalign(1.0);
-            value.style_context().add_class("value");
+pub struct InfoView {
+    renderer: Box<dyn InfoRenderer>,
+    cells: HashMap<String, InfoCell>,
+    order: Vec<String>,
+}
 
-            innerbox.add(&icon);
-            innerbox.add(&icon_label);
-            innerbox.add(&value);
+impl InfoView {
+    pub fn new(renderer: Box<dyn InfoRenderer>, items: &[InfoItem]) -> Self {
+        let mut view = InfoView {
+            renderer,
+            cells: HashMap::new(),
+            order: Vec::new(),
+        };
+        for item in items {
+            view.add_item(item);
+        }
+        view
+    }
 
-            inforow.add(&innerbox);
+    pub fn widget(&self) -> &gtk::Widget {
+        self.renderer.container().upcast_ref()
+    }
 
-            rows.insert(id.clone(), (icon, icon_label, value));
+    pub fn add_item(&mut self, item: &InfoItem) -> &Self {
+        if self.cells.contains_key(&item.id) {
+            self.update(item);
+            return self;
         }
+        let cell = self.renderer.build_cell(item);
+        self.renderer.attach(&cell, self.order.len());
+        for part in &cell.parts {
+            part.show_all();
+        }
+        // after show_all, that would show the glyph label again
+        cell.apply(item);
+        self.order.push(item.id.clone());
+        self.cells.insert(item.id.clone(), cell);
+        self.update_class_by_item_count();
+        self
+    }
 
-        Self {
-            container: inforow.upcast(),
-            rows,
+    pub fn update(&self, item: &InfoItem) -> &Self {
+        if let Some(cell) = self.cells.get(&item.id) {
+            cell.set_label(&item.label);
+            cell.apply(item);
         }
+        self
     }
 
-    fn widget(&self) -> &gtk::Widget {
-        &self.container.upcast_ref()
+    pub fn update_value(&self, id: &str, new_value: &str) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.set_value(new_value);
+        }
+        self
     }
 
-    fn update_value(&self, id: &str, new_value: &str) -> &Self {
-        if let Some((_, _, value_label)) = self.rows.get(id) {
-            value_label.set_text(new_value);
+    pub fn update_path(&self, id: &str, new_icon_path: &str) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.set_path(new_icon_path);
         }
-        &self
+        self
     }
 
-    fn update_path(&self, id: &str, new_icon_path: &str) -> &Self {
-        if let Some((icon, _, _)) = self.rows.get(id) {
-            // icon.set_from_file(Some(new_icon_path));
-            let pixbuf = Pixbuf::from_file_at_size(new_icon_path, ICONSIZE, ICONSIZE).unwrap();
-            icon.set_from_pixbuf(Some(&pixbuf));
-            icon.set_pixel_size(ICONSIZE);
+    pub fn update_color(&self, id: &str, color_css: &str) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.set_color(color_css);
         }
-        &self
+        self
     }
 
-    fn update_color(&self, id: &str, color_css: &str) -> &Self {
-        if let Some((_, icon_label, value_label)) = self.rows.get(id) {
-            value_label.set_markup(&format!(r#"<span foreground="{}">{}</span>"#, color_css, glib::markup_escape_text(&value_label.text())));
-            icon_label.set_markup(&format!(r#"<span foreground="{}">{}</span>"#, color_css, glib::markup_escape_text(&icon_label.text())));
+    // Accepts a glyph, a path to an image or "theme:<icon-name>"
+    pub fn update_icon(&self, id: &str, icon: &str) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.set_icon(icon);
         }
-        &self
+        self
     }
 
-    fn update_icon(&self, id: &str, icon_text: &str) -> &Self {
-        if let Some((_, icon_label, _)) = self.rows.get(id) {
-            icon_label.set_text(icon_text);
+    pub fn update_progress(&self, id: &str, progress: f64) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.set_progress(progress);
+        }
+        self
+    }
+
+    pub fn update_class(&self, id: &str, class: &str) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.set_class(class);
+        }
+        self
+    }
+
+    pub fn update_sparkline(&self, id: &str, points: Vec<(f64, String)>) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.set_points(points);
+        }
+        self
+    }
+
+    pub fn connect_scroll<F: Fn(i32) + 'static>(&self, id: &str, callback: F) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.connect_scroll(callback);
+        }
+        self
+    }
+
+    pub fn connect_click<F: Fn() + 'static>(&self, id: &str, callback: F) -> &Self {
+        if let Some(cell) = self.cells.get(id) {
+            cell.connect_click(callback);
+        }
+        self
+    }
+
+    fn update_class_by_item_count(&self) {
+        let context = self.renderer.container().style_context();
+        for cls in context.list_classes() {
+            if cls.starts_with("items-") {
+                context.remove_class(&cls);
+            }
         }
-        &self
+        context.add_class(&format!("items-{}", self.order.len()));
     }
 }
@@ -0,0 +1,257 @@
+use gio::prelude::*;
+use gio::{AppInfo, AppInfoCreateFlags};
+use glib::ToVariant;
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::consts::APP_NAME;
+use crate::error::Error;
+use crate::util::get_cache_file;
+
+const STDERR_TAIL_LINES: usize = 8;
+const STDERR_TAIL_CHARS: usize = 800;
+const NOTIFY_TIMEOUT_MS: i32 = 2000;
+
+// How a child gets started: its extra environment and, when watched, where its stderr goes
+#[derive(Default)]
+pub struct LaunchContext {
+    pub env: Vec<(String, String)>,
+    pub watch: Duration,
+    pub stderr_log: Option<PathBuf>,
+}
+
+impl LaunchContext {
+    // A watch of 0 seconds leaves the child alone, like before
+    pub fn new(key: &str, watch_secs: u64) -> LaunchContext {
+        let stderr_log = if watch_secs > 0 { get_cache_file(&format!("launches/{}.log", file_name(key)), true) } else { None };
+        LaunchContext { env: Vec::new(), watch: Duration::from_secs(watch_secs), stderr_log }
+    }
+
+    // Lets the compositor focus the new window although it's started from an overlay that's going away:
+    // GDK hands out an xdg-activation token on Wayland and a startup id on X11, so ask while the window is still up
+    pub fn activate(&mut self, info: Option<&AppInfo>, command_line: &str) {
+        if let Some(token) = activation_token(info, command_line) {
+            self.env.push(("XDG_ACTIVATION_TOKEN".into(), token.clone()));
+            self.env.push(("DESKTOP_STARTUP_ID".into(), token));
+        }
+    }
+
+    pub fn spawn(&self, command: &mut Command) -> std::io::Result<Child> {
+        // Whatever we were started with has been used up already
+        command.env_remove("XDG_ACTIVATION_TOKEN").env_remove("DESKTOP_STARTUP_ID");
+        command.envs(self.env.iter().map(|(k, v)| (k, v)));
+        // A file rather than a pipe: the app outlives us and must not get SIGPIPE once nobody reads
+        if let Some(log) = &self.stderr_log {
+            command.stderr(File::create(log).map(Stdio::from).unwrap_or_else(|_| Stdio::inherit()));
+        }
+        command.spawn()
+    }
+}
+
+fn activation_token(info: Option<&AppInfo>, command_line: &str) -> Option<String> {
+    let context: Option<gdk::AppLaunchContext> = gdk::Display::default()?.app_launch_context().into();
+    let info = match info {
+        Some(info) => info.clone(),
+        None => AppInfo::create_from_commandline(command_line, None, AppInfoCreateFlags::NONE).ok()?,
+    };
+    context?.startup_notify_id(&info, &[]).map(|id| id.to_string())
+}
+
+fn file_name(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+// Waits up to ctx.watch for the child to fail, keeping the application alive meanwhile even though
+// the window is gone. A non-zero exit becomes a notification with the end of its stderr
+pub fn watch_launch(application: &gtk::Application, mut child: Child, name: String, ctx: &LaunchContext) {
+    if ctx.watch.is_zero() {
+        return;
+    }
+    let mut guard = Some(application.hold());
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let (watch, waited) = (ctx.watch, name.clone());
+    std::thread::spawn(move || {
+        let start = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(None) if start.elapsed() < watch => std::thread::sleep(Duration::from_millis(100)),
+                Ok(status) => break status,
+                Err(err) => {
+                    log_warn!("Cannot wait for {}: {}", waited, err);
+                    break None;
+                }
+            }
+        };
+        let _ = sender.send(status);
+    });
+    let stderr_log = ctx.stderr_log.clone();
+    receiver.attach(None, move |status| {
+        let (summary, body) = match status.filter(|s| !s.success()) {
+            Some(status) => failure_message(&name, status, stderr_log.as_deref()),
+            None => return glib::Continue(false),
+        };
+        log_error!("{}\n{}", summary, body);
+        match gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>) {
+            // The application is released once the daemon answered (or didn't in time)
+            Ok(connection) => {
+                let guard = guard.take();
+                notify(&connection, &summary, &body, move |result| {
+                    if let Err(err) = result {
+                        log_warn!("Cannot send notification: {}", err);
+                    }
+                    drop(guard);
+                });
+            }
+            Err(err) => log_warn!("Cannot send notification: {}", Error::command("D-Bus session bus", err)),
+        }
+        glib::Continue(false)
+    });
+}
+
+fn failure_message(name: &str, status: ExitStatus, stderr_log: Option<&Path>) -> (String, String) {
+    (format!("{} {}", name, describe(status)), stderr_log.map(stderr_tail).unwrap_or_default())
+}
+
+fn describe(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exited with code {}", code),
+        (None, Some(signal)) => format!("was killed by signal {}", signal),
+        _ => "failed".into(),
+    }
+}
+
+fn stderr_tail(log: &Path) -> String {
+    let s = std::fs::read_to_string(log).unwrap_or_default();
+    let lines: Vec<&str> = s.lines().collect();
+    let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+    let skip = tail.chars().count().saturating_sub(STDERR_TAIL_CHARS);
+    tail.chars().skip(skip).collect()
+}
+
+// org.freedesktop.Notifications.Notify, any daemon (or a stub one on a private bus) will do.
+// Asynchronous with a short timeout, a missing daemon mustn't stall the main loop
+pub fn notify<F: FnOnce(Result<(), Error>) + 'static>(connection: &gio::DBusConnection, summary: &str, body: &str, done: F) {
+    let mut hints: HashMap<String, glib::Variant> = HashMap::new();
+    hints.insert("urgency".into(), 2u8.to_variant());
+    let actions: Vec<String> = Vec::new();
+    let params = (APP_NAME, 0u32, "dialog-error", summary, body, actions, hints, -1i32).to_variant();
+    connection.call(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+        "Notify",
+        Some(&params),
+        None,
+        gio::DBusCallFlags::NONE,
+        NOTIFY_TIMEOUT_MS,
+        None::<&gio::Cancellable>,
+        move |result| done(result.map(|_| ()).map_err(|err| Error::command("Notify", err))),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const NOTIFICATIONS_XML: &str = r#"<node>
+        <interface name="org.freedesktop.Notifications">
+            <method name="Notify">
+                <arg type="s" direction="in"/><arg type="u" direction="in"/><arg type="s" direction="in"/>
+                <arg type="s" direction="in"/><arg type="s" direction="in"/><arg type="as" direction="in"/>
+                <arg type="a{sv}" direction="in"/><arg type="i" direction="in"/><arg type="u" direction="out"/>
+            </method>
+        </interface>
+    </node>"#;
+
+    fn connect(address: &str) -> gio::DBusConnection {
+        gio::DBusConnection::for_address_sync(
+            address,
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            None::<&gio::Cancellable>,
+        )
+        .unwrap()
+    }
+
+    // Owns org.freedesktop.Notifications on the bus and keeps (summary, body) of every Notify
+    fn stub_daemon(address: &str) -> (gio::DBusConnection, Rc<RefCell<Vec<(String, String)>>>) {
+        let connection = connect(address);
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let node = gio::DBusNodeInfo::for_xml(NOTIFICATIONS_XML).unwrap();
+        let interface = node.lookup_interface("org.freedesktop.Notifications").unwrap();
+        let stored = received.clone();
+        connection
+            .register_object(
+                "/org/freedesktop/Notifications",
+                &interface,
+                move |_, _, _, _, _, params, invocation| {
+                    let text = |i| params.child_value(i).get::<String>().unwrap_or_default();
+                    stored.borrow_mut().push((text(3), text(4)));
+                    invocation.return_value(Some(&(1u32,).to_variant()));
+                },
+                |_, _, _, _, _| "".to_variant(),
+                |_, _, _, _, _, _| false,
+            )
+            .unwrap();
+        connection
+            .call_sync(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "RequestName",
+                Some(&("org.freedesktop.Notifications", 0u32).to_variant()),
+                None,
+                gio::DBusCallFlags::NONE,
+                -1,
+                None::<&gio::Cancellable>,
+            )
+            .unwrap();
+        (connection, received)
+    }
+
+    fn notify_and_wait(address: &str, summary: &str, body: &str) -> Result<(), Error> {
+        let done = Rc::new(RefCell::new(None));
+        let result = done.clone();
+        notify(&connect(address), summary, body, move |r| *result.borrow_mut() = Some(r));
+        let context = glib::MainContext::default();
+        while done.borrow().is_none() {
+            context.iteration(true);
+        }
+        let result = done.borrow_mut().take();
+        result.unwrap()
+    }
+
+    // One test, the default main context can't be iterated by two test threads at once
+    #[test]
+    fn failed_launch_is_notified_with_stderr_tail() {
+        let bus = gio::TestDBus::new(gio::TestDBusFlags::NONE);
+        bus.up();
+        let address = bus.bus_address().unwrap().to_string();
+
+        let log = std::env::temp_dir().join(format!("skoll-launch-test-{}.log", std::process::id()));
+        let ctx = LaunchContext { stderr_log: Some(log.clone()), ..LaunchContext::default() };
+        let mut child = ctx.spawn(Command::new("sh").args(["-c", "echo first >&2; echo boom >&2; exit 3"])).unwrap();
+        let status = child.wait().unwrap();
+        let (summary, body) = failure_message("broken-app", status, Some(&log));
+        let _ = std::fs::remove_file(&log);
+        assert_eq!(summary, "broken-app exited with code 3");
+        assert_eq!(body, "first\nboom");
+
+        // Nobody owns the name yet: an error right away, not a hang
+        assert!(notify_and_wait(&address, &summary, &body).is_err());
+
+        let (_daemon, received) = stub_daemon(&address);
+        assert!(notify_and_wait(&address, &summary, &body).is_ok());
+        assert_eq!(*received.borrow(), vec![(summary, body)]);
+        bus.down();
+    }
+}
@@ -15,10 +15,12 @@ You should have received a copy of the GNU General Public License
 along with sirula.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use libc::{setlocale, strcoll};
+use libc::{iconv, iconv_close, iconv_open, setlocale, strcoll};
 pub use locale_types::{LocaleIdentifier};
 use std::{
+    cell::RefCell,
     cmp::{Ord, Ordering},
+    collections::HashMap,
     ffi::{CStr, CString},
     os::raw::c_char,
     ptr,
@@ -53,4 +55,114 @@ pub fn set_locale(category: i32, locale: &str) -> Option<String> {
 #[allow(unused)]
 pub fn get_locale(category: i32) -> Option<String> {
     unsafe { setlocale_wrapper(category, ptr::null()) }
-}
\ No newline at end of file
+}
+
+thread_local! {
+    static TRANSLITERATIONS: RefCell<HashMap<char, Option<String>>> = RefCell::new(HashMap::new());
+}
+
+// Like strcoll this follows LC_CTYPE: glibc's //TRANSLIT turns É into E, æ into ae, ß into ss
+fn transliterate(c: char) -> Option<String> {
+    let to = CString::new("ASCII//TRANSLIT").unwrap();
+    let from = CString::new("UTF-8").unwrap();
+    let mut input = [0u8; 4];
+    let input = c.encode_utf8(&mut input);
+    let mut output = [0u8; 16];
+    let written = unsafe {
+        let cd = iconv_open(to.as_ptr(), from.as_ptr());
+        if cd as isize == -1 {
+            return None;
+        }
+        let mut in_ptr = input.as_mut_ptr() as *mut c_char;
+        let mut in_left = input.len();
+        let mut out_ptr = output.as_mut_ptr() as *mut c_char;
+        let mut out_left = output.len();
+        let ret = iconv(cd, &mut in_ptr, &mut in_left, &mut out_ptr, &mut out_left);
+        iconv_close(cd);
+        if ret == usize::MAX {
+            return None;
+        }
+        output.len() - out_left
+    };
+    // Anything without a transliteration (glyphs, CJK) comes back as '?' and is left alone
+    let folded = std::str::from_utf8(&output[..written]).ok()?;
+    if folded.is_empty() || folded.contains('?') {
+        None
+    } else {
+        Some(folded.to_string())
+    }
+}
+
+// Returns the folded string and, for each of its chars, the index of the char it comes from
+pub fn fold_diacritics(s: &str) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(s.len());
+    let mut map = Vec::with_capacity(s.len());
+    for (i, c) in s.chars().enumerate() {
+        if c.is_ascii() {
+            folded.push(c);
+            map.push(i);
+            continue;
+        }
+        let replacement = TRANSLITERATIONS.with(|cache| {
+            cache.borrow_mut().entry(c).or_insert_with(|| transliterate(c)).clone()
+        });
+        match replacement {
+            Some(replacement) => {
+                for r in replacement.chars() {
+                    folded.push(r);
+                    map.push(i);
+                }
+            }
+            None => {
+                folded.push(c);
+                map.push(i);
+            }
+        }
+    }
+    (folded, map)
+}
+
+// Pango wants byte offsets, matchers give char indices
+pub fn char_to_byte_ranges(s: &str, indices: &[usize]) -> Vec<(u32, u32)> {
+    let offsets: Vec<usize> = s.char_indices().map(|(b, _)| b).chain(std::iter::once(s.len())).collect();
+    indices
+        .iter()
+        .filter(|i| **i + 1 < offsets.len())
+        .map(|i| (offsets[*i] as u32, offsets[*i + 1] as u32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_ranges_of_multibyte_chars() {
+        assert_eq!(char_to_byte_ranges("Ärger", &[0, 1]), vec![(0, 2), (2, 3)]);
+        assert_eq!(char_to_byte_ranges("café", &[2, 3]), vec![(2, 3), (3, 5)]);
+        // Past the end is dropped rather than panicking
+        assert_eq!(char_to_byte_ranges("café", &[4]), vec![]);
+    }
+
+    #[test]
+    fn folding_points_back_at_the_original_chars() {
+        // Without a UTF-8 LC_CTYPE iconv can't transliterate and the chars are kept as they are
+        set_locale(libc::LC_CTYPE, "C.UTF-8");
+        for s in ["Ärger", "café", "Straße"] {
+            let (folded, map) = fold_diacritics(s);
+            assert_eq!(map.len(), folded.chars().count());
+            let ranges = char_to_byte_ranges(s, &map);
+            assert_eq!(ranges.len(), map.len());
+            assert!(ranges.iter().all(|(start, end)| s.is_char_boundary(*start as usize) && s.is_char_boundary(*end as usize)));
+        }
+        let (folded, map) = fold_diacritics("Straße");
+        if folded.is_ascii() {
+            assert_eq!(folded, "Strasse");
+            assert_eq!(map, vec![0, 1, 2, 3, 4, 4, 5]);
+            // Highlighting "ss" marks the ß once
+            let mut marked = map[4..6].to_vec();
+            marked.dedup();
+            assert_eq!(char_to_byte_ranges("Straße", &marked), vec![(4, 6)]);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// Leveled messages on stderr, the level comes from SKOLL_LOG (e.g. SKOLL_LOG=debug or SKOLL_LOG=info,profile).
+// stdout is left alone, other programs may read it
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn parse(s: &str) -> Option<Level> {
+        match s {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" | "trace" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warning",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+pub fn init() {
+    let level = std::env::var("SKOLL_LOG")
+        .ok()
+        .and_then(|value| value.split(',').map(str::trim).find_map(Level::parse));
+    if let Some(level) = level {
+        MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn log(level: Level, args: fmt::Arguments) {
+    if enabled(level) {
+        eprintln!("{}: {}", level.tag(), args);
+    }
+}
+
+macro_rules! log_error {
+    ($($arg:tt)+) => ($crate::logging::log($crate::logging::Level::Error, format_args!($($arg)+)));
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)+) => ($crate::logging::log($crate::logging::Level::Warn, format_args!($($arg)+)));
+}
+
+macro_rules! log_info {
+    ($($arg:tt)+) => ($crate::logging::log($crate::logging::Level::Info, format_args!($($arg)+)));
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)+) => ($crate::logging::log($crate::logging::Level::Debug, format_args!($($arg)+)));
+}
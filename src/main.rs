@@ -17,20 +17,25 @@ along with sirula.  If not, see <https://www.gnu.org/licenses/>.
 
 */
 
-use fuzzy_matcher::skim::SkimMatcherV2;
 use gdk::keys::constants;
 use gio::prelude::*;
 use gtk::{
     builders::{
         BoxBuilder, EntryBuilder, LabelBuilder, ListBoxBuilder, ScrolledWindowBuilder
-    }, prelude::*, ListBoxRow
+    }, prelude::*
 };
 use libc::LC_ALL;
 use serde_derive::Deserialize;
 use std::env::args;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::{Cell, RefCell}, collections::HashMap, rc::Rc, sync::{atomic::{AtomicU64, Ordering}, Arc}};
 use std::fs;
 
+#[macro_use]
+mod logging;
+
+mod error;
+use error::*;
+
 mod consts;
 use consts::*;
 
@@ -49,12 +54,43 @@ use locale::*;
 mod history;
 use history::*;
 
+mod history_cli;
+
+mod matcher;
+use matcher::*;
+
+mod bench;
+
 mod niri;
 use niri::*;
 
 mod infogrid;
 use infogrid::*;
 
+mod controls;
+
+mod metrics;
+use metrics::*;
+
+mod palette;
+use palette::*;
+
+mod sparkline;
+use sparkline::*;
+
+mod icons;
+
+mod rows;
+use rows::*;
+
+mod launch;
+use launch::*;
+
+mod profile;
+
+mod weather;
+use weather::*;
+
 use std::process::{Command, Stdio};
 // use std::error::Error;
 
@@ -66,43 +102,25 @@ use sysinfo::{Disks, System};
 
 use bytesize::ByteSize;
 
-use std::time::Instant;
-
 /* pub fn get_from_map<'a, K: Eq + std::hash::Hash, V>(map: &'a HashMap<K, V>, key: &K) -> Option<&'a V> {
     map.get(key) // .expect(&format!("Key not found in map"))
 } */
 
 static TEST_COLORS: bool = false;
 
-#[derive(Deserialize)]
-pub struct WeatherObj {
-    pub icon: String,
-    pub icon_name: String,
-    pub temp: i8,
-    pub temp_real: i8,
-    pub temp_unit: String,
-    pub text: String,
-    pub day: String,
-    pub sunrise: String,
-    pub sunset: String,
-    pub sunrise_mins: u64,
-    pub sunset_mins: u64,
-    pub daylight: f64,
-    pub locality: String,
-    pub humidity: u8
-}
-
 #[derive(Deserialize)]
 pub struct VolumeObj {
     pub icon: String,
-    pub value: i8,
-    pub clazz: String
+    pub value: u16,
+    pub clazz: String,
+    #[serde(default)]
+    pub muted: bool
 }
 
 #[derive(Deserialize)]
 pub struct BrightnessObj {
     pub icon: String,
-    pub percentage: i8,
+    pub percentage: u16,
     pub clazz: String
 }
 
@@ -117,9 +135,83 @@ pub struct NetworkObj {
     pub wifi: i8
 }
 
-fn app_startup(application: &gtk::Application) {
+fn finish_match(
+    batch: MatchBatch,
+    entries: &RefCell<Vec<AppEntry>>,
+    pool: &RefCell<RowPool>,
+    listbox: &gtk::ListBox,
+    sections: &Sections,
+    config: &Config,
+    last_match: &RefCell<Option<(String, Query, Vec<bool>)>>,
+) {
+    {
+        let mut entries = entries.borrow_mut();
+        for (entry, result) in entries.iter_mut().zip(&batch.results) {
+            entry.set_match(result.as_ref());
+            entry.learned = entry.app_id()
+                .and_then(|id| batch.boosts.get(&id).copied())
+                .unwrap_or(0);
+        }
+        sync_rows(&mut entries, pool, listbox, sections, batch.text_empty, config);
+    }
+    let matched = batch.results.iter().map(Option::is_some).collect();
+    last_match.replace(Some((batch.mode, batch.query, matched)));
+
+    listbox.select_row(pool.borrow().first_row().as_ref());
+}
+
+fn show_error(label: &gtk::Label, message: &str) {
+    log_error!("{}", message);
+    label.set_text(message);
+    label.show();
+}
+
+fn update_pins(
+    history: &Rc<RefCell<History>>,
+    entries: &RefCell<Vec<AppEntry>>,
+    pool: &RefCell<RowPool>,
+    listbox: &gtk::ListBox,
+    sections: &Sections,
+    query_empty: bool,
+    config: &Config,
+) {
+    let pinned = load_history(0).pinned;
+    let mut entries = entries.borrow_mut();
+    apply_pins(&mut entries, &pinned);
+    sync_rows(&mut entries, pool, listbox, sections, query_empty, config);
+    history.borrow_mut().pinned = pinned;
+}
+
+// "@" lists the categories, "@Office " browses one of them and the rest of the text filters its apps.
+// Returns what follows the prefix, the category being browsed and the search text
+fn split_query<'a>(text: &'a str, category_prefix: &str) -> (Option<&'a str>, Option<&'a str>, &'a str) {
+    let browse = text.strip_prefix(category_prefix).filter(|_| !category_prefix.is_empty());
+    match browse {
+        Some(rest) => match rest.split_once(' ') {
+            Some((category, search)) => (browse, Some(category), search),
+            None => (browse, None, rest),
+        },
+        None => (None, None, text),
+    }
+}
+
+// Only the best max_rows visible entries get one of the pooled rows, in list order
+fn sync_rows(
+    entries: &mut [AppEntry],
+    pool: &RefCell<RowPool>,
+    listbox: &gtk::ListBox,
+    sections: &Sections,
+    query_empty: bool,
+    config: &Config,
+) {
+    let _span = profile::span("sync_rows");
+    sections.apply_limits(entries, query_empty);
+    pool.borrow_mut().sync(entries, sections, query_empty, config);
+    listbox.invalidate_headers();
+}
 
-    let t0 = Instant::now();
+fn app_startup(application: &gtk::Application) {
+    let startup_span = profile::span("app_startup");
 
     // Stampa le finestre
     // println!("Finestre aperte:");
@@ -133,10 +225,22 @@ fn app_startup(application: &gtk::Application) {
     } */
 
 
-    let config = Config::load();
-    let config2 = Config::load();
+    let config_span = profile::span("config_load");
+    let (config, config_error) = match Config::try_load() {
+        Ok(config) => (config, None),
+        Err(err) => (Config::default(), Some(err)),
+    };
+    drop(config_span);
+    set_palette(Palette::from_config(&config.palette, &config.palette_stops));
+    if config.palette_css {
+        load_palette_css(current_palette());
+    }
     let launch_cgroups = config.cgroups;
     let cmd_prefix = config.command_prefix.clone();
+    let metric_rules = resolve_metrics(&config.metrics, &METRIC_IDS);
+    let weather_provider = provider_from_config(&config.weather);
+    let weather_ttl = config.weather.ttl;
+    let weather_icons_dir = config.weather.icons_dir.clone();
 
     let window = gtk::ApplicationWindow::new(application);
     window.fullscreen();
@@ -189,45 +293,51 @@ fn app_startup(application: &gtk::Application) {
         .build();
 
         let mut info_items = vec![
-            ("loadavg".into(), "Load avg".into(), "󰬢".into(), "".into()),
-            ("ram".into(), "RAM".into(), "󰍛".into(), "".into()),
-            // ("swap".into(), "SWAP".into(), "󰍛".into(), "".into()),
-            ("disk".into(), "Main disk".into(), "󰋊".into(), "".into()),
-            ("weather".into(), "Weather".into(), "".into(), "".into()),
-            // ("cpu".into(), "CPU".into(), "IC".into(), "/path/to/icons/cpu.png".into()),
-            ("volume".into(), "Volume".into(), "󱄡".into(), "".into()),
-            ("brightness".into(), "Brightness".into(), "󱧤".into(), "".into()),
-            ("temp".into(), "Temperature".into(), "󱤋".into(), "".into()),
-            ("network".into(), "Network".into(), "󰲊".into(), "".into()),
-            // ("col0".into(), "col0".into(), "".into(), "".into()),
-            // ("col1".into(), "col1".into(), "".into(), "".into()),
-            // ("col2".into(), "col1".into(), "".into(), "".into()),
-            // ("col3".into(), "col1".into(), "".into(), "".into()),
-            // ("col4".into(), "col1".into(), "".into(), "".into()),
-            // ("col5".into(), "col1".into(), "".into(), "".into()),
-            // ("col6".into(), "col1".into(), "".into(), "".into()),
-            // ("col7".into(), "col1".into(), "".into(), "".into()),
-            // ("col8".into(), "col1".into(), "".into(), "".into()),
-            // ("col9".into(), "col1".into(), "".into(), "".into()),
-            // ("col10".into(), "col11".into(), "".into(), "".into())
+            InfoItem::new("loadavg", "Load avg", "󰬢"),
+            InfoItem::new("ram", "RAM", "󰍛"),
+            // InfoItem::new("swap", "SWAP", "󰍛"),
+            InfoItem::new("disk", "Main disk", "󰋊"),
+            InfoItem::new("weather", "Weather", ""),
+            InfoItem::new("sun", "Daylight", "󰖜"),
+            InfoItem::new("humidity", "Humidity", "󰖎"),
+            // InfoItem::new("cpu", "CPU", "IC"),
+            InfoItem::new("volume", "Volume", "󱄡"),
+            InfoItem::new("brightness", "Brightness", "󱧤"),
+            InfoItem::new("temp", "Temperature", "󱤋"),
+            InfoItem::new("network", "Network", "󰲊"),
+            // InfoItem::new("col0", "col0", ""),
+            // InfoItem::new("col1", "col1", ""),
+            // InfoItem::new("col2", "col1", ""),
+            // InfoItem::new("col3", "col1", ""),
+            // InfoItem::new("col4", "col1", ""),
+            // InfoItem::new("col5", "col1", ""),
+            // InfoItem::new("col6", "col1", ""),
+            // InfoItem::new("col7", "col1", ""),
+            // InfoItem::new("col8", "col1", ""),
+            // InfoItem::new("col9", "col1", ""),
+            // InfoItem::new("col10", "col11", "")
         ];
+        // Weather, daylight and humidity all come from the weather provider
+        if weather_provider.is_none() {
+            info_items.retain(|item| !["weather", "sun", "humidity"].contains(&item.id.as_str()));
+        }
         if TEST_COLORS {
             let colors_test = vec![
-                ("col0".into(), "col0".into(), "".into(), "".into()),
-                ("col1".into(), "col1".into(), "".into(), "".into()),
-                ("col2".into(), "col1".into(), "".into(), "".into()),
-                ("col3".into(), "col1".into(), "".into(), "".into()),
-                ("col4".into(), "col1".into(), "".into(), "".into()),
-                ("col5".into(), "col1".into(), "".into(), "".into()),
-                ("col6".into(), "col1".into(), "".into(), "".into()),
-                ("col7".into(), "col1".into(), "".into(), "".into()),
-                ("col8".into(), "col1".into(), "".into(), "".into()),
-                ("col9".into(), "col1".into(), "".into(), "".into()),
-                ("col10".into(), "col11".into(), "".into(), "".into())
+                InfoItem::new("col0", "col0", ""),
+                InfoItem::new("col1", "col1", ""),
+                InfoItem::new("col2", "col1", ""),
+                InfoItem::new("col3", "col1", ""),
+                InfoItem::new("col4", "col1", ""),
+                InfoItem::new("col5", "col1", ""),
+                InfoItem::new("col6", "col1", ""),
+                InfoItem::new("col7", "col1", ""),
+                InfoItem::new("col8", "col1", ""),
+                InfoItem::new("col9", "col1", ""),
+                InfoItem::new("col10", "col11", "")
             ];
             info_items.extend_from_slice(&colors_test);
         }
-        let info_grid = InfoBar::new(&info_items);
+        let info_grid = InfoView::new(renderer_by_name(&config.info_layout, config.info_icon_size), &info_items);
         container.add(info_grid.widget());
 
         if TEST_COLORS {
@@ -258,6 +368,19 @@ fn app_startup(application: &gtk::Application) {
     let entry = EntryBuilder::new().name(SEARCH_ENTRY_NAME).build(); // .width_request(300)
     search_container.pack_start(&entry, false, false, 0);
 
+    // Failures show up here instead of taking the launcher down, the next keystroke hides them
+    let error_label = LabelBuilder::new()
+        .name(ERROR_LABEL_NAME)
+        .xalign(0.0f32)
+        .wrap(true)
+        .no_show_all(true)
+        .visible(false)
+        .build();
+    search_container.pack_start(&error_label, false, false, 0);
+    if let Some(err) = &config_error {
+        show_error(&error_label, &format!("{}, using the default config", err));
+    }
+
     let scroll = ScrolledWindowBuilder::new()
         .name(SCROLL_NAME)
         .hscrollbar_policy(gtk::PolicyType::Never)
@@ -267,28 +390,50 @@ fn app_startup(application: &gtk::Application) {
     let listbox = ListBoxBuilder::new().name(LISTBOX_NAME).build();
     scroll.add(&listbox);
 
+    let history_span = profile::span("history_load");
     let history = Rc::new(RefCell::new(load_history(config.prune_history)));
+    let query_history = Rc::new(RefCell::new(load_query_history(config.prune_history)));
+    drop(history_span);
+
+    let niri_span = profile::span("niri_query");
+    let (windows, workspaces_map) = match get_niri_windows() {
+        Ok((windows, workspaces_map)) => {
+            // Removed on disk too, otherwise the next save would merge the closed windows back in.
+            // The history was just loaded, if nothing went here there's nothing to write
+            let ids: Vec<String> = windows.iter().map(|w| w.id.to_string()).collect();
+            if history.borrow_mut().retain_windows(&ids) {
+                edit_history(|stored| {
+                    stored.retain_windows(&ids);
+                });
+            }
+            (windows, workspaces_map)
+        }
+        Err(err) => {
+            show_error(&error_label, &format!("Open windows are not listed: {}", err));
+            (Vec::new(), HashMap::new())
+        }
+    };
+    drop(niri_span);
 
-    let tn0 = Instant::now();
-    let (windows, workspaces_map) = get_niri_windows();
-    let tn1 = Instant::now();
-    let entry_windows_hash_map = load_entries_running(&config, windows, workspaces_map);
-    let tn2 = Instant::now();
-
-    println!("⏱️ get_niri_windows: {:?}", tn1 - tn0);
-    println!("⏱️ compute_niri_entries: {:?}", tn2 - tn1);
-
-    let mut entry_hash_map = load_entries(&config, &history.borrow());
+    let entries_span = profile::span("entry_building");
+    let entry_windows = load_entries_running(&config, &history.borrow(), windows, workspaces_map);
+    let mut all_entries = load_entries(&config, &history.borrow());
 
-    entry_hash_map.extend(entry_windows_hash_map);
+    all_entries.extend(entry_windows);
+    all_entries.extend(load_entries_commands(&config, &history.borrow()));
+    let category_entries = load_entries_categories(&config, &all_entries);
+    all_entries.extend(category_entries);
+    apply_pins(&mut all_entries, &history.borrow().pinned);
+    drop(entries_span);
 
-    let entries = Rc::new(RefCell::new(entry_hash_map));
+    let sections = Rc::new(Sections::from_config(&config));
+    let pool = Rc::new(RefCell::new(RowPool::new(&listbox)));
+    sync_rows(&mut all_entries, &pool, &listbox, &sections, true, &config);
 
-    for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
-        listbox.add(row);
-    }
+    let entries = Rc::new(RefCell::new(all_entries));
+    let config = Rc::new(config);
 
-    window.connect_key_press_event(clone!(entry, listbox, entries => move |window, event| {
+    window.connect_key_press_event(clone!(entry, listbox, entries, pool, history, query_history, sections, config => move |window, event| {
         use constants::*;
         #[allow(non_upper_case_globals)]
         Inhibit(match event.keyval() {
@@ -296,6 +441,52 @@ fn app_startup(application: &gtk::Application) {
                 window.close();
                 true
             },
+            // Only from the list, in the search entry Ctrl+Delete deletes the next word
+            Delete | KP_Delete if event.state().contains(gdk::ModifierType::CONTROL_MASK) && !entry.has_focus() => {
+                // Forget the selected entry: it goes back to where it would be without history
+                if let Some(index) = listbox.selected_row().and_then(|row| pool.borrow().entry_at(&row)) {
+                    let mut es = entries.borrow_mut();
+                    let e = &mut es[index];
+                    let (kind, history_id) = (e.kind, e.history_id.clone());
+                    history.borrow_mut().kind_mut(kind).remove(&history_id);
+                    edit_history(|h| { h.kind_mut(kind).remove(&history_id); });
+                    if let Some(id) = e.app_id() {
+                        forget_query_choices(&mut query_history.borrow_mut(), &id);
+                        edit_query_history(|h| forget_query_choices(h, &id));
+                    }
+                    e.history = HistoryData::default();
+                    e.boost = 0;
+                    e.learned = 0;
+                    sync_rows(&mut es, &pool, &listbox, &sections, entry.text().is_empty(), &config);
+                }
+                true
+            },
+            p | P if event.state().contains(gdk::ModifierType::CONTROL_MASK) => {
+                if let Some(index) = listbox.selected_row().and_then(|row| pool.borrow().entry_at(&row)) {
+                    let key = entries.borrow()[index].pin_key();
+                    edit_history(|h| h.toggle_pin(&key));
+                    update_pins(&history, &entries, &pool, &listbox, &sections, entry.text().is_empty(), &config);
+                }
+                true
+            },
+            Up | Down if event.state().contains(gdk::ModifierType::CONTROL_MASK) => {
+                // Reorders the pinned section
+                let index = listbox.selected_row().and_then(|row| pool.borrow().entry_at(&row));
+                let key = index.and_then(|i| {
+                    let es = entries.borrow();
+                    es[i].pinned.map(|_| es[i].pin_key())
+                });
+                // Anything else keeps the usual Ctrl+Up/Down of the list
+                match key {
+                    Some(key) => {
+                        let delta = if event.keyval() == Up { -1 } else { 1 };
+                        edit_history(|h| h.move_pin(&key, delta));
+                        update_pins(&history, &entries, &pool, &listbox, &sections, entry.text().is_empty(), &config);
+                        true
+                    }
+                    None => false,
+                }
+            },
             Down | KP_Down | Tab if entry.has_focus() => {
 /*let (windows, workspaces_map) = get_niri_windows();
 //let tn1 = Instant::now();
@@ -309,19 +500,13 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
 }*/
 
                 if let Some(r0) = listbox.row_at_index(0) {
-                    let es = entries.borrow();
+                    let pool = pool.borrow();
                     if r0.is_selected() {
-                        if let Some(r1) = listbox.row_at_index(1) {
-                            if let Some(app_entry) = es.get(&r1) {
-                                if !app_entry.hidden() {
-                                    listbox.select_row(Some(&r1));
-                                }
-                            }
-                        }
-                    } else if let Some(app_entry) = es.get(&r0) {
-                        if !app_entry.hidden() {
-                            listbox.select_row(Some(&r0));
+                        if let Some(r1) = listbox.row_at_index(1).filter(|r| pool.entry_at(r).is_some()) {
+                            listbox.select_row(Some(&r1));
                         }
+                    } else if pool.entry_at(&r0).is_some() {
+                        listbox.select_row(Some(&r0));
                     }
                 }
                 false
@@ -345,84 +530,181 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
     	});
     }
 
-    let matcher = SkimMatcherV2::default();
+    let category_prefix = config.category_prefix.clone();
+    let matcher = Arc::new(Matcher::from_config(&config));
     let term_command = config.term_command.clone();
-    entry.connect_changed(clone!(entries, listbox, cmd_prefix => move |e| {
+    let frecency_half_life = config.frecency_half_life;
+    let launch_watch = config.launch_watch;
+
+    // Plain copies of what gets matched, in the same order as entries, so matching can leave the GTK thread
+    let candidates: Arc<Vec<Candidate>> = Arc::new(entries.borrow().iter().map(AppEntry::candidate).collect());
+    let last_match: Rc<RefCell<Option<(String, Query, Vec<bool>)>>> = Rc::new(RefCell::new(None));
+    let generation = Arc::new(AtomicU64::new(0));
+
+    let (match_sender, match_receiver) = glib::MainContext::channel::<MatchBatch>(glib::PRIORITY_DEFAULT);
+    match_receiver.attach(None, clone!(entries, pool, listbox, sections, config, last_match, generation => move |batch| {
+        // A newer keystroke already started, this one is stale
+        if batch.generation == generation.load(Ordering::Relaxed) {
+            finish_match(batch, &entries, &pool, &listbox, &sections, &config, &last_match);
+        }
+        glib::Continue(true)
+    }));
+    let match_worker = MatchWorker::spawn(matcher.clone(), candidates.clone(), generation.clone(), match_sender);
+
+    entry.connect_changed(clone!(entries, pool, listbox, cmd_prefix, query_history, sections, config, error_label => move |e| {
+        error_label.hide();
         let text = e.text();
         let is_cmd = is_cmd(&text, &cmd_prefix);
-        {
-            let mut entries = entries.borrow_mut();
-            for entry in entries.values_mut() {
-                if is_cmd {
-                    entry.hide(); // hide entries in command mode
-                } else {
-                    entry.update_match(&text, &matcher, &config);
+        let (browse, category, search) = split_query(&text, &config.category_prefix);
+        let mode = format!("{}|{}|{}", is_cmd, browse.is_some(), category.unwrap_or_default());
+        let text_empty = text.is_empty();
+        let query = matcher.parse(search);
+        let boosts = query_boosts(&query_history.borrow(), search, now_secs(), config.frecency_half_life, config.query_learning_weight);
+
+        let mut mask: Vec<bool> = {
+            let entries = entries.borrow();
+            entries.iter().map(|entry| match (browse, category) {
+                _ if is_cmd => false, // hide entries in command mode
+                (Some(_), Some(category)) => {
+                    entry.kind == HistoryKind::Launch && entry.categories.iter().any(|c| c.eq_ignore_ascii_case(category))
+                }
+                (Some(_), None) => entry.category.is_some(),
+                (None, _) => entry.category.is_none() || (text.is_empty() && config.categories_on_empty),
+            }).collect()
+        };
+        if let Some((last_mode, last_query, matched)) = &*last_match.borrow() {
+            if *last_mode == mode && query.narrows(last_query) {
+                for (m, previous) in mask.iter_mut().zip(matched) {
+                    *m &= *previous;
                 }
             }
         }
-        listbox.invalidate_filter();
-        listbox.invalidate_sort();
-        listbox.select_row(listbox.row_at_index(0).as_ref());
+
+        // Whatever the worker is busy with is stale from now on
+        let generation = generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let tried = mask.iter().filter(|m| **m).count();
+        if tried >= config.async_match_threshold && !query.is_empty() {
+            match_worker.submit(MatchBatch { generation, mode, text_empty, query, results: Vec::new(), boosts }, mask);
+        } else {
+            let results = matcher.match_all(&query, &candidates, &mask);
+            finish_match(MatchBatch { generation, mode, text_empty, query, results, boosts }, &entries, &pool, &listbox, &sections, &config, &last_match);
+        }
     }));
 
-    entry.connect_activate(clone!(listbox, window => move |e| {
+    entry.connect_activate(clone!(pool, window, history, error_label => move |e| {
         let text = e.text();
         if is_cmd(&text, &cmd_prefix) { // command execution direct
             let cmd_line = &text[cmd_prefix.len()..].trim();
-            launch_cmd(cmd_line);
+            let mut ctx = LaunchContext::new(cmd_line, launch_watch);
+            ctx.activate(None, cmd_line);
+            let child = match launch_cmd(cmd_line, &ctx) {
+                Ok(child) => child,
+                Err(err) => {
+                    show_error(&error_label, &err.to_string());
+                    return;
+                }
+            };
+            let mut history = history.borrow_mut();
+            history.record(HistoryKind::Command, cmd_line, frecency_half_life);
+            save_history(&mut history);
+            if let Some(application) = window.application() {
+                watch_launch(&application, child, cmd_line.to_string(), &ctx);
+            }
             window.close();
-        } else if let Some(row) = listbox.row_at_index(0) {
-            row.activate();
+        } else {
+            // Not borrowed while activating: a category row sets the text, which syncs the rows again
+            let first = pool.borrow().first_row();
+            if let Some(row) = first {
+                row.activate();
+            }
         }
     }));
 
-    listbox.connect_row_activated(clone!(entries, window, history => move |_, r| {
+    listbox.connect_row_activated(clone!(entries, pool, window, history, query_history, entry, category_prefix, error_label => move |_, r| {
+        let index = match pool.borrow().entry_at(r) {
+            Some(index) => index,
+            None => return,
+        };
         let es = entries.borrow();
-        let e = &es[r];
+        let e = &es[index];
+        if let Some(category) = &e.category {
+            // Drill down, the changed handler does the filtering
+            let text = format!("{}{} ", category_prefix, category);
+            drop(es);
+            entry.set_text(&text);
+            entry.set_position(-1);
+            entry.grab_focus_without_selecting();
+            return;
+        }
         if !e.hidden() {
-            match &e.custom_cmd {
-                Some(cmd) => {
-                    let cmd_parts: Vec<&str> = cmd.split_whitespace().collect();
-                    Command::new(&cmd_parts[0])
-                        .args(&cmd_parts[1..])
-                        .spawn()
-                        .expect("Error focusing open app");
-                }
-                _ => {
-                    launch_app(&e.info, term_command.as_deref(), launch_cgroups);
+            // Focusing a window isn't worth watching
+            let mut ctx = LaunchContext::new(&e.history_id, if e.custom_cmd.is_some() { 0 } else { launch_watch });
+            if e.custom_cmd.is_none() {
+                ctx.activate(e.info.as_ref(), &e.history_id);
+            }
+            let launched = match &e.custom_cmd {
+                Some(cmd) => launch_cmd(cmd, &ctx).map(Some),
+                _ if e.kind == HistoryKind::Command => launch_cmd(&e.history_id, &ctx).map(Some),
+                _ => match &e.info {
+                    Some(info) => launch_app(info, term_command.as_deref(), launch_cgroups, &ctx).map(Some),
+                    None => Ok(None),
+                },
+            };
+            let child = match launched {
+                Ok(child) => child,
+                Err(err) => {
+                    show_error(&error_label, &err.to_string());
+                    return;
                 }
+            };
+            if let (Some(child), Some(application)) = (child, window.application()) {
+                let name = e.info.as_ref().map(|info| info.display_name().to_string()).unwrap_or_else(|| e.history_id.clone());
+                watch_launch(&application, child, name, &ctx);
             }
 
             let mut history = history.borrow_mut();
-            update_history(&mut history, e.info.id().unwrap().as_str());
-            save_history(&history);
+            history.record(e.kind, &e.history_id, frecency_half_life);
+            save_history(&mut history);
+
+            if let Some(id) = e.app_id() {
+                let mut query_history = query_history.borrow_mut();
+                // The same text query_boosts looks up, without the category
+                let text = entry.text();
+                let (_, _, search) = split_query(&text, &category_prefix);
+                update_query_history(&mut query_history, search, &id, frecency_half_life);
+                save_query_history(&mut query_history);
+            }
 
             window.close();
         }
     }));
 
-    listbox.set_filter_func(Some(Box::new(clone!(entries => move |r| {
-        let e = entries.borrow();
-        !e[r].hidden()
-        // true
-    }))));
-
-    listbox.set_sort_func(Some(Box::new(clone!(entries => move |a, b| {
-        let e = entries.borrow();
-        // e[a].cmp(&e[b]) as i32
-
-        if let (Some(e_a), Some(e_b)) = (e.get(a), e.get(b)) {
-            if e_a.display == e_b.display {
-                e_a.cmp(&e_b) as i32
-            } else {
-                e_b.display.cmp(&e_a.display) as i32
+    if sections.headers {
+        listbox.set_header_func(Some(Box::new(clone!(pool => move |row, before| {
+            // Busy means a sync is running, it invalidates the headers when it's done
+            let pool = match pool.try_borrow() {
+                Ok(pool) => pool,
+                Err(_) => return,
+            };
+            let title = pool.header_at(row);
+            if title.is_none() || title == before.and_then(|r| pool.header_at(r)) {
+                row.set_header(None::<&gtk::Widget>);
+                return;
             }
-        } else {
-            0
-        }
-    }))));
+            let title = title.unwrap();
+            // Reuse the label when the row keeps its section
+            if let Some(label) = row.header().and_then(|h| h.downcast::<gtk::Label>().ok()) {
+                if label.text() == title {
+                    return;
+                }
+            }
+            let label = LabelBuilder::new().label(&title).xalign(0.0f32).build();
+            label.style_context().add_class("section-header");
+            row.set_header(Some(&label));
+        }))));
+    }
 
-    listbox.select_row(listbox.row_at_index(0).as_ref());
+    listbox.select_row(pool.borrow().first_row().as_ref());
 
 
 
@@ -506,16 +788,11 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
         SysUpdate::Error("Disk not found".to_string())
     } */
 
-    fn get_weather () -> SysUpdate {
-        let output = Command::new("/home/vncnz/.config/eww/scripts/meteo.sh").arg("'Desenzano Del Garda'").arg("45.457692").arg("10.570684").output();
-        let stdout = String::from_utf8(output.unwrap().stdout).unwrap();
-        // println!("\n{:?}", stdout);
-        // let weather: WeatherObj;
-        if let Ok(weather) = serde_json::from_str(&stdout) {
-            SysUpdate::Weather(weather)
-        } else {
-            SysUpdate::Error("Error with serde and weather data".to_string())}
-        
+    fn get_weather_update (provider: &dyn WeatherProvider, ttl: u64) -> SysUpdate {
+        match get_weather(provider, ttl) {
+            Ok(weather) => SysUpdate::Weather(weather),
+            Err(err) => SysUpdate::Error(format!("Weather: {}", err))
+        }
     }
 
     /* fn get_volume () -> SysUpdate {
@@ -530,10 +807,19 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
     } */
 
     fn get_brightness () -> SysUpdate {
-        let output = Command::new("/home/vncnz/.config/eww/scripts/brightness.sh").arg("json").output();
-        let stdout = String::from_utf8(output.unwrap().stdout).unwrap();
+        if let Some(percentage) = controls::get_brightness() {
+            return SysUpdate::Brightness(BrightnessObj {
+                icon: controls::brightness_icon(percentage).into(),
+                percentage,
+                clazz: String::new()
+            });
+        }
+        let output = match Command::new("/home/vncnz/.config/eww/scripts/brightness.sh").arg("json").output() {
+            Ok(output) => output,
+            Err(err) => return SysUpdate::Error(format!("Brightness: {}", err)),
+        };
         // println!("\n{:?}", stdout);
-        if let Ok(brightness) = serde_json::from_str(&stdout) {
+        if let Ok(brightness) = serde_json::from_slice(&output.stdout) {
             SysUpdate::Brightness(brightness)
         } else {
             SysUpdate::Error("Error with serde and brightness data".to_string())
@@ -541,13 +827,18 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
     }
 
     fn spawn_network_monitor (sender: glib::Sender<SysUpdate>) {
-        let mut child = Command::new("/home/vncnz/.config/eww/scripts/network.sh")
+        let child = Command::new("/home/vncnz/.config/eww/scripts/network.sh")
             .arg(&"json")
             .stdout(Stdio::piped())
-            .spawn()
-            .expect("Failed to spawn network monitor");
-    
-        let stdout = child.stdout.take().expect("Failed to open stdout");
+            .spawn();
+        let stdout = match child.map(|mut child| child.stdout.take()) {
+            Ok(Some(stdout)) => stdout,
+            Ok(None) => return,
+            Err(err) => {
+                let _ = sender.send(SysUpdate::Error(format!("Network monitor: {}", err)));
+                return;
+            }
+        };
         let reader = BufReader::new(stdout);
     
         std::thread::spawn(move || {
@@ -562,7 +853,7 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                         }
                     }
                     Err(err) => {
-                        eprintln!("Errore lettura output network: {}", err);
+                        log_warn!("Errore lettura output network: {}", err);
                         break;
                     }
                 }
@@ -585,7 +876,8 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
         SysUpdate::Error("Temperature Tctl not found".to_string())
     } */
 
-    fn get2 (sender: glib::Sender<SysUpdate>) {
+    fn get2 () -> Vec<SysUpdate> {
+        let mut updates = Vec::new();
         if let Ok(contents) = fs::read_to_string("/tmp/ratatoskr.json") {
             let res: Result<Value, serde_json::Error> = serde_json::from_str(&contents);
             if let Ok(data) = res {
@@ -595,9 +887,9 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                     data["ram"]["total_swap"].as_u64(),
                     data["ram"]["used_swap"].as_u64()
                 ] {
-                    sender.send(SysUpdate::RAM(tm, um, ts, us)).expect("Send error");
+                    updates.push(SysUpdate::RAM(tm, um, ts, us));
                 } else {
-                    println!("File opened, ram not found");
+                    log_warn!("File opened, ram not found");
                 }
 
                 if let (Some(m1), Some(m5), Some(m15), color) = (
@@ -607,33 +899,21 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                     data["loadavg"]["color"].as_str()
                 ) {
                     let c = if let Some(col) = color { Some(col.to_string()) } else { None };
-                    sender.send(SysUpdate::LoadAvg(m1, m5, m15, c)).expect("Send error");
+                    updates.push(SysUpdate::LoadAvg(m1, m5, m15, c));
                 } else {
-                    println!("File opened, loadavg not found");
+                    log_warn!("File opened, loadavg not found");
                 }
 
                 if let (Some(name), Some(value)) = (
                     data["temperature"]["sensor"].as_str(),
                     data["temperature"]["value"].as_f64()
                  ) {
-                    sender.send(SysUpdate::Temperature(name.to_string(), value as f32)).expect("Send error");
+                    updates.push(SysUpdate::Temperature(name.to_string(), value as f32));
                 } else {
-                    println!("File opened, temperature not found");
+                    log_warn!("File opened, temperature not found");
                 }
 
-                if let (Some(icon), Some(value), Some(clazz)) = (
-                    data["volume"]["icon"].as_str(),
-                    data["volume"]["value"].as_f64(),
-                    data["volume"]["clazz"].as_str()
-                 ) {
-                    sender.send(SysUpdate::Volume(VolumeObj {
-                        icon: icon.to_string(),
-                        value: value as i8,
-                        clazz: clazz.to_string()
-                    })).expect("Send error");
-                } else {
-                    println!("File opened, volume not found");
-                }
+                // Volume comes from the pactl subscription, which also knows about mute
 
                 if let (Some(total), Some(used), Some(percent), color) = (
                     data["disk"]["total_size"].as_u64(),
@@ -642,22 +922,36 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                     data["disk"]["color"].as_str()
                  ) {
                     let c = if let Some(col) = color { Some(col.to_string()) } else { None };
-                    sender.send(SysUpdate::Disk(total, used, percent, c)).expect("Send error");
+                    updates.push(SysUpdate::Disk(total, used, percent, c));
                 } else {
-                    println!("File opened, disk not found");
+                    log_warn!("File opened, disk not found");
                 }
 
             } else {
                 // File exists but contains shit
-                println!("File exists but contains shit");
+                log_warn!("File exists but contains shit");
             }
         } else {
             // No file
-            println!("No file");
+            log_warn!("No file");
         }
+        updates
     }
 
-    /* fn spawn_volume_monitor(sender: glib::Sender<SysUpdate>) {
+    fn get_current_volume () -> SysUpdate {
+        if let Some(state) = controls::get_volume() {
+            SysUpdate::Volume(VolumeObj {
+                icon: controls::volume_icon(state.value, state.muted).into(),
+                value: state.value,
+                clazz: String::new(),
+                muted: state.muted
+            })
+        } else {
+            SysUpdate::Error("Cannot read volume from pactl".to_string())
+        }
+    }
+
+    fn spawn_volume_monitor(sender: glib::Sender<SysUpdate>) {
         std::thread::spawn(move || {
             let child = Command::new("pactl")
                 .arg("subscribe")
@@ -670,24 +964,73 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                     for line in reader.lines() {
                         if let Ok(line) = line {
                             if line.contains("sink") {
-                                let volume = get_current_volume(); // definisci tu
-                                let _ = sender.send(SysUpdate::Volume(volume));
+                                let _ = sender.send(get_current_volume());
                             }
                         }
                     }
                 }
             } else {
-                eprintln!("Errore nell'eseguire pactl subscribe");
+                log_warn!("Errore nell'eseguire pactl subscribe");
             }
         });
-    } */
+    }
 
-    
+    enum ControlAction {
+        Volume(i32),
+        ToggleMute,
+        Brightness(i32)
+    }
+
+    // pactl and the backlight writes block, so they run here and not in the scroll and click handlers
+    fn spawn_controls(sender: glib::Sender<SysUpdate>) -> std::sync::mpsc::Sender<ControlAction> {
+        let (control_sender, actions) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for action in actions {
+                let update = match action {
+                    ControlAction::Volume(delta) => {
+                        controls::change_volume(delta);
+                        get_current_volume()
+                    }
+                    ControlAction::ToggleMute => {
+                        controls::toggle_mute();
+                        get_current_volume()
+                    }
+                    ControlAction::Brightness(delta) => {
+                        controls::change_brightness(delta);
+                        get_brightness()
+                    }
+                };
+                if sender.send(update).is_err() {
+                    break;
+                }
+            }
+        });
+        control_sender
+    }
 
     let (sender, receiver) = glib::MainContext::channel::<SysUpdate>(glib::PRIORITY_DEFAULT);
+    let control_sender = spawn_controls(sender.clone());
+
+    info_grid
+        .connect_scroll("volume", clone!(control_sender => move |step| {
+            let _ = control_sender.send(ControlAction::Volume(step * 5));
+        }))
+        .connect_click("volume", clone!(control_sender => move || {
+            let _ = control_sender.send(ControlAction::ToggleMute);
+        }))
+        .connect_scroll("brightness", clone!(control_sender => move |step| {
+            let _ = control_sender.send(ControlAction::Brightness(step * 5));
+        }));
 
     // get2(sender.clone());
 
+    let rule = move |id: &str| metric_rules.get(id).cloned().unwrap_or_else(|| default_rule(id));
+
+    let mut metric_history: HashMap<&str, MetricHistory> = ["loadavg", "ram", "temp", "network"]
+        .iter()
+        .map(|id| (*id, MetricHistory::new(SPARKLINE_SAMPLES)))
+        .collect();
+
     // In main thread: connessione all'aggiornamento
     receiver.attach(None, move |info: SysUpdate| {
         match info {
@@ -706,10 +1049,17 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                     (&*format!("{}", m5), m5/max * 100.0, m5color),
                     (&*format!("{}", m15), m15/max * 100.0, m15color)
                 ].to_vec()); */
-                let color = if rat_color == None { get_color_gradient(1.2, 2., m1/m5, false) } else { rat_color.unwrap() };
+                let load_rule = rule("loadavg");
+                let color = if rat_color == None { load_rule.color(m1/m5) } else { rat_color.unwrap() };
                 info_grid
                     .update_value("loadavg", &*format!("[{:.2} {:.2} {:.2}]", m1, m5, m15))
-                    .update_color("loadavg", &color);
+                    .update_color("loadavg", &color)
+                    .update_class("loadavg", load_rule.state(m1/m5));
+
+                if let Some(hist) = metric_history.get_mut("loadavg") {
+                    let max = f64::max(hist.push(m1).max(), 1.0);
+                    info_grid.update_sparkline("loadavg", hist.points(0.0, max, |v| get_color_gradient(0.0, max, v, false)));
+                }
             },
             SysUpdate::RAM(tm, um, ts, us) => {
                 // let umh = ByteSize::b(um).display().iec().to_string();
@@ -717,7 +1067,8 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                 let tsh = ByteSize::b(ts).display().iec().to_string();
                 // let uwh = ByteSize::b(uw).display().iec().to_string();
                 let memory_ratio = um as f64 / tm as f64;
-                let memory_color = get_color_gradient(60.0, 90.0, memory_ratio * 100.0, false);
+                let ram_rule = rule("ram");
+                let memory_color = ram_rule.color(memory_ratio * 100.0);
 
                 let swap_ratio = us as f64 / ts as f64;
                 // let swap_color = get_color_gradient(40.0, 90.0, swap_ratio * 100.0);
@@ -735,6 +1086,13 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
 
                 info_grid.update_value("ram", &*format!("M: {:.0}% of {}\nS: {:.0}% of {}", memory_ratio * 100.0, tmh, swap_ratio * 100.0, tsh));
                 info_grid.update_color("ram", &memory_color);
+                info_grid.update_class("ram", ram_rule.state(memory_ratio * 100.0));
+                info_grid.update_progress("ram", memory_ratio * 100.0);
+
+                if let Some(hist) = metric_history.get_mut("ram") {
+                    hist.push(memory_ratio * 100.0);
+                    info_grid.update_sparkline("ram", hist.points(0.0, 100.0, |v| ram_rule.color(v)));
+                }
 
                 // info_grid.update_value("swap", &*format!("{:.0}% of {}", swap_ratio * 100.0, tsh));
                 // info_grid.update_color("swap", &swap_color);
@@ -746,51 +1104,82 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
                 // range_sys_disk_clone.set_value(disk_ratio * 100.0);
                 // apply_scale_color(&range_sys_disk_clone, &disk_color);
                 // label_sys_disk_clone.set_markup(&format!("<span foreground=\"{}\">󰋊 {:.0}% of {} on {}</span>", disk_color, disk_ratio * 100.0, totalh, name));
-                let color = if rat_color == None { get_color_gradient(60.0, 90.0, percent as f64, false) } else { rat_color.unwrap() };
+                let disk_rule = rule("disk");
+                let color = if rat_color == None { disk_rule.color(percent as f64) } else { rat_color.unwrap() };
 
                 info_grid.update_value("disk", &*format!("{:.0}% of {}", percent as f64, totalh));
                 info_grid.update_color("disk", &color);
+                info_grid.update_class("disk", disk_rule.state(percent as f64));
+                info_grid.update_progress("disk", percent as f64);
             },
             SysUpdate::Weather(weather) => {
-                let temp_text = format!("{}{}", weather.temp, weather.temp_unit);
+                let temp_text = format!("{}{} ({}{})", weather.temp, weather.temp_unit, weather.temp_real, weather.temp_unit);
                 info_grid.update_value("weather", &temp_text);
-                info_grid.update_path("weather", &format!("/home/vncnz/.config/eww/images/weather/{}", weather.icon_name));
+                // A directory of images named after the freedesktop icons, what it lacks comes from the icon theme
+                match weather_icons_dir.as_deref().map(|dir| weather_icon_file(dir, &weather.icon_name)) {
+                    Some(Some(file)) => info_grid.update_path("weather", &file.to_string_lossy()),
+                    Some(None) => info_grid.update_icon("weather", &format!("theme:{}", weather.icon_name)),
+                    None => info_grid.update_icon("weather", &weather.icon),
+                };
+                info_grid.update_value("sun", &format!("{} - {} ({:.1}h)", weather.sunrise, weather.sunset, weather.daylight));
+                info_grid.update_value("humidity", &format!("{}%", weather.humidity));
+                let weather_rule = rule("weather");
+                info_grid.update_color("weather", &weather_rule.color(weather.temp as f64));
+                info_grid.update_class("weather", weather_rule.state(weather.temp as f64));
             },
             SysUpdate::Volume(volume) => {
-                let text = if volume.value == 0 { "Muted".into() } else { format!("{}%", volume.value) };
-                let volume_color = get_color_gradient(40.0, 100.0, volume.value as f64, false);
+                let text = if volume.muted || volume.value == 0 { "Muted".into() } else { format!("{}%", volume.value) };
+                let volume_rule = rule("volume");
+                let volume_color = volume_rule.color(volume.value as f64);
                 info_grid.update_value("volume", &text);
                 info_grid.update_icon("volume", &*volume.icon);
                 info_grid.update_color("volume", &volume_color);
+                info_grid.update_class("volume", volume_rule.state(volume.value as f64));
+                info_grid.update_progress("volume", if volume.muted { 0.0 } else { volume.value as f64 });
             },
             SysUpdate::Brightness(brightness) => {
                 let text = format!("{}%", brightness.percentage);
-                // let brightness_color = get_color_gradient(30.0, 60.0, brightness.percentage as f64);
+                let brightness_rule = rule("brightness");
                 info_grid.update_value("brightness", &text);
                 info_grid.update_icon("brightness", &*brightness.icon);
-                // info_grid.update_color("brightness", &brightness_color);
+                info_grid.update_color("brightness", &brightness_rule.color(brightness.percentage as f64));
+                info_grid.update_class("brightness", brightness_rule.state(brightness.percentage as f64));
+                info_grid.update_progress("brightness", brightness.percentage as f64);
             },
             SysUpdate::Temperature(sensor, value) => {
                 let text = format!("{:.0}°C", value);
-                let temp_color = get_color_gradient(80.0, 99.0, value as f64, false);
+                let temp_rule = rule("temp");
+                let temp_color = temp_rule.color(value as f64);
                 info_grid.update_value("temp", &text);
-                let icon = if value < 80.0 { "" } else 
-                                         if value < 85.0 { "" } else
-                                         if value < 90.0 { "" } else
-                                         if value < 95.0 { "" } else { "" };
-                info_grid.update_icon("temp", icon);
+                if let Some(icon) = temp_rule.icon(value as f64) {
+                    info_grid.update_icon("temp", icon);
+                }
                 info_grid.update_color("temp", &temp_color);
+                info_grid.update_class("temp", temp_rule.state(value as f64));
+
+                if let Some(hist) = metric_history.get_mut("temp") {
+                    hist.push(value as f64);
+                    info_grid.update_sparkline("temp", hist.points(30.0, 100.0, |v| temp_rule.color(v)));
+                }
             },
             SysUpdate::Network(net) => {
                 let text = format!("{}%", net.signal);
-                let color = get_color_gradient(20.0, 60.0, net.signal as f64, true);
+                let network_rule = rule("network");
+                let color = network_rule.color(net.signal as f64);
                 info_grid.update_value("network", &text);
                 info_grid.update_icon("network", &net.icon);
                 // info_grid.update_icon("temp", "");
                 info_grid.update_color("network", &color);
+                info_grid.update_class("network", network_rule.state(net.signal as f64));
+                info_grid.update_progress("network", net.signal as f64);
+
+                if let Some(hist) = metric_history.get_mut("network") {
+                    hist.push(net.signal as f64);
+                    info_grid.update_sparkline("network", hist.points(0.0, 100.0, |v| network_rule.color(v)));
+                }
             },
             SysUpdate::Error(error) => {
-                println!("ERROR: {}", error);
+                log_warn!("{}", error);
             }
         }
         // sysdata.loadavg = Some(info);
@@ -800,20 +1189,31 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
 
 
     spawn_network_monitor(sender.clone());
+    spawn_volume_monitor(sender.clone());
 
     std::thread::spawn(move || {
         // sender.send(get_disk_info()).expect("Send failed");
-        sender.send(get_weather()).expect("Send failed");
         // sender.send(get_weather()).expect("Send failed");
+        let weather_every = (weather_ttl / 2).max(1);
         let mut counter = 0;
         loop {
+            let mut updates = Vec::new();
+            if let Some(provider) = weather_provider.as_ref().filter(|_| counter % weather_every == 0) {
+                updates.push(get_weather_update(provider.as_ref(), weather_ttl));
+            }
             // if counter % 2 == 0 { sender.send(get_load_avg()).expect("Send failed") };
             // if counter % 2 == 0 { sender.send(get_ram_info()).expect("Send failed") };
             // if counter % 2 == 0 { sender.send(get_sys_temperatures()).expect("Send failed") };
             // sender.send(get_volume()).expect("Send failed");
-            sender.send(get_brightness()).expect("Send failed");
+            updates.push(get_brightness());
+
+            if counter % 2 == 0 { updates.extend(get2()) };
 
-            if counter % 2 == 0 { get2(sender.clone()) };
+            // The receiver goes away with the main loop
+            if updates.into_iter().any(|update| sender.send(update).is_err()) {
+                log_debug!("Info updates have no receiver anymore, stopping");
+                break;
+            }
 
             counter += 1;
             std::thread::sleep(std::time::Duration::from_secs(2));
@@ -828,18 +1228,21 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
             window.set_size_request(geometry.width(), geometry.height());
             window.move_(geometry.x(), geometry.y());
         } else {
-            println!("\n\nNO MONITOR\n\n");
+            log_error!("No monitor");
         }
     } else {
-        println!("\n\nNO DISPLAY\n\n");
+        log_error!("No display");
     }
 
-    let t1 = Instant::now();
-    println!("⏱️ app_startup:        {:?}", t1 - t0);
+    drop(startup_span);
 
-    window.connect_realize(move |_| {
-        let t_realized = Instant::now();
-        println!("🖼️ Window realized at {:?}", t_realized - t0);
+    window.connect_realize(|_| profile::mark("window_realized"));
+    let first_draw = Cell::new(true);
+    window.connect_draw(move |_, _| {
+        if first_draw.replace(false) {
+            profile::mark("first_draw");
+        }
+        Inhibit(false)
     });
 
     window.show_all()
@@ -847,6 +1250,12 @@ for row in (&entries.borrow() as &HashMap<ListBoxRow, AppEntry>).keys() {
 
 fn main() {
     set_locale(LC_ALL, "");
+    logging::init();
+
+    let argv = profile::init(args().collect());
+    if let Some(code) = history_cli::run_history_command(&argv).or_else(|| bench::run_bench_command(&argv)) {
+        std::process::exit(code);
+    }
 
     let application = gtk::Application::new(Some(APP_ID), Default::default());
 
@@ -859,5 +1268,6 @@ fn main() {
         //do nothing
     });
 
-    application.run_with_args(&args().collect::<Vec<_>>());
+    application.run_with_args(&argv);
+    profile::report();
 }
\ No newline at end of file
@@ -0,0 +1,345 @@
+use fuzzy_matcher::clangd::ClangdMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+
+use crate::locale::fold_diacritics;
+use crate::Config;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Strategy {
+    Skim,
+    Clangd,
+    Substring,
+    WordPrefix,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CaseMode {
+    Smart,
+    Ignore,
+    Respect,
+}
+
+// What the user typed, parsed once per keystroke and then matched against every entry
+#[derive(Clone)]
+pub enum Query {
+    Empty,
+    Text { pattern: String, case_sensitive: bool },
+    Regex(Regex),
+    InvalidRegex,
+}
+
+impl Query {
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Query::Empty)
+    }
+
+    // Typing one more char can only lose matches, so only the previous ones need to be tried again
+    pub fn narrows(&self, previous: &Query) -> bool {
+        match (self, previous) {
+            (
+                Query::Text { pattern, case_sensitive },
+                Query::Text { pattern: previous, case_sensitive: previous_case },
+            ) => case_sensitive == previous_case && pattern.starts_with(previous.as_str()),
+            _ => false,
+        }
+    }
+}
+
+// What gets matched, without any GTK object so it can be moved to another thread
+#[derive(Clone)]
+pub struct Candidate {
+    pub display: String,
+    pub search: String,
+    pub hidden: Vec<(f64, String)>,
+}
+
+pub type MatchResult = Option<(i64, Vec<usize>)>;
+
+// Results of one keystroke, with what's needed to apply them once back on the GTK thread
+pub struct MatchBatch {
+    pub generation: u64,
+    pub mode: String,
+    // Pins and empty-query sections follow the whole text, not just the query part
+    pub text_empty: bool,
+    pub query: Query,
+    pub results: Vec<MatchResult>,
+    pub boosts: HashMap<String, i64>,
+}
+
+// Big lists are matched on one long-lived thread. Keystrokes are numbered by the shared generation:
+// the worker skips queued ones that a newer keystroke made pointless and gives up on a stale one halfway
+pub struct MatchWorker {
+    sender: mpsc::Sender<(MatchBatch, Vec<bool>)>,
+}
+
+// Candidates matched between two looks at the generation
+const MATCH_CHUNK: usize = 256;
+
+impl MatchWorker {
+    pub fn spawn(
+        matcher: Arc<Matcher>,
+        candidates: Arc<Vec<Candidate>>,
+        generation: Arc<AtomicU64>,
+        done: glib::Sender<MatchBatch>,
+    ) -> MatchWorker {
+        let (sender, receiver) = mpsc::channel::<(MatchBatch, Vec<bool>)>();
+        std::thread::spawn(move || {
+            while let Ok(mut job) = receiver.recv() {
+                while let Ok(newer) = receiver.try_recv() {
+                    job = newer;
+                }
+                let (mut batch, mask) = job;
+                let wanted = batch.generation;
+                let stale = || generation.load(Ordering::Relaxed) != wanted;
+                if let Some(results) = matcher.match_until(&batch.query, &candidates, &mask, stale) {
+                    batch.results = results;
+                    if done.send(batch).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        MatchWorker { sender }
+    }
+
+    // batch.results is filled in by the worker
+    pub fn submit(&self, batch: MatchBatch, mask: Vec<bool>) {
+        let _ = self.sender.send((batch, mask));
+    }
+}
+
+pub struct Matcher {
+    strategy: Strategy,
+    case: CaseMode,
+    regex_prefix: String,
+    fold: bool,
+    name_weight: f64,
+    skim: SkimMatcherV2,
+    clangd: ClangdMatcher,
+}
+
+impl Matcher {
+    pub fn from_config(config: &Config) -> Matcher {
+        let strategy = match config.matcher.as_str() {
+            "fuzzy" | "skim" => Strategy::Skim,
+            "clangd" => Strategy::Clangd,
+            "substring" | "exact" => Strategy::Substring,
+            "prefix" | "word_prefix" | "initials" => Strategy::WordPrefix,
+            other => {
+                log_warn!("Unknown matcher {}, using fuzzy", other);
+                Strategy::Skim
+            }
+        };
+        let case = match config.case_mode.as_str() {
+            "smart" => CaseMode::Smart,
+            "ignore" => CaseMode::Ignore,
+            "respect" => CaseMode::Respect,
+            other => {
+                log_warn!("Unknown case mode {}, using smart", other);
+                CaseMode::Smart
+            }
+        };
+        let (skim, clangd) = match case {
+            CaseMode::Smart => (SkimMatcherV2::default().smart_case(), ClangdMatcher::default().smart_case()),
+            CaseMode::Ignore => (SkimMatcherV2::default().ignore_case(), ClangdMatcher::default().ignore_case()),
+            CaseMode::Respect => (SkimMatcherV2::default().respect_case(), ClangdMatcher::default().respect_case()),
+        };
+        Matcher { strategy, case, regex_prefix: config.regex_prefix.clone(), fold: config.fold_diacritics, name_weight: config.field_weight("name"), skim, clangd }
+    }
+
+    fn case_sensitive(&self, pattern: &str) -> bool {
+        match self.case {
+            CaseMode::Smart => pattern.chars().any(char::is_uppercase),
+            CaseMode::Ignore => false,
+            CaseMode::Respect => true,
+        }
+    }
+
+    pub fn parse(&self, input: &str) -> Query {
+        if input.is_empty() {
+            return Query::Empty;
+        }
+        if !self.regex_prefix.is_empty() {
+            if let Some(expr) = input.strip_prefix(self.regex_prefix.as_str()) {
+                if expr.is_empty() {
+                    return Query::Empty;
+                }
+                return match RegexBuilder::new(expr).case_insensitive(!self.case_sensitive(expr)).build() {
+                    Ok(regex) => Query::Regex(regex),
+                    Err(_) => Query::InvalidRegex,
+                };
+            }
+        }
+        let pattern = if self.fold { fold_diacritics(input).0 } else { input.to_string() };
+        Query::Text { pattern, case_sensitive: self.case_sensitive(input) }
+    }
+
+    // Indices are char positions in haystack, like fuzzy-matcher returns them
+    pub fn indices(&self, query: &Query, haystack: &str) -> Option<(i64, Vec<usize>)> {
+        match query {
+            Query::Empty => Some((0, Vec::new())),
+            Query::InvalidRegex => None,
+            Query::Regex(regex) => regex_indices(regex, haystack),
+            Query::Text { pattern, case_sensitive } => {
+                if !self.fold || haystack.is_ascii() {
+                    return self.text_indices(haystack, pattern, *case_sensitive);
+                }
+                // Match on the folded name, then point back at the original chars
+                let (folded, map) = fold_diacritics(haystack);
+                self.text_indices(&folded, pattern, *case_sensitive).map(|(score, indices)| {
+                    let mut indices: Vec<usize> = indices.iter().filter_map(|i| map.get(*i).copied()).collect();
+                    indices.dedup();
+                    (score, indices)
+                })
+            }
+        }
+    }
+
+    // Highest weighted score among the fields, only the displayed one gets highlighted.
+    // The joined string is the last resort, for queries spanning more than one field
+    pub fn best_match(&self, query: &Query, candidate: &Candidate) -> MatchResult {
+        if query.is_empty() {
+            return Some((100, Vec::new()));
+        }
+        let weighted = |score: i64, weight: f64| ((score as f64 * weight).round() as i64).max(1);
+        let mut best = self
+            .indices(query, &candidate.display)
+            .map(|(score, indices)| (weighted(score, self.name_weight), indices));
+        for (weight, field) in &candidate.hidden {
+            if let Some((score, _)) = self.indices(query, field) {
+                let score = weighted(score, *weight);
+                if best.as_ref().map_or(true, |(b, _)| score > *b) {
+                    best = Some((score, Vec::new()));
+                }
+            }
+        }
+        best.or_else(|| {
+            let weight = candidate.hidden.iter().map(|(w, _)| *w).fold(self.name_weight, f64::min);
+            self.indices(query, &candidate.search)
+                .map(|(score, indices)| (weighted(score, weight), indices))
+        })
+    }
+
+    // Candidates left out by the mask don't match
+    pub fn match_all(&self, query: &Query, candidates: &[Candidate], mask: &[bool]) -> Vec<MatchResult> {
+        self.match_until(query, candidates, mask, || false).unwrap_or_default()
+    }
+
+    // None when stop() says the results aren't wanted anymore
+    pub fn match_until<F: Fn() -> bool>(
+        &self,
+        query: &Query,
+        candidates: &[Candidate],
+        mask: &[bool],
+        stop: F,
+    ) -> Option<Vec<MatchResult>> {
+        let mut results = Vec::with_capacity(candidates.len());
+        for (i, (candidate, try_it)) in candidates.iter().zip(mask).enumerate() {
+            if i % MATCH_CHUNK == 0 && stop() {
+                return None;
+            }
+            results.push(if *try_it { self.best_match(query, candidate) } else { None });
+        }
+        Some(results)
+    }
+
+    fn text_indices(&self, haystack: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+        match self.strategy {
+            Strategy::Skim => self.skim.fuzzy_indices(haystack, pattern),
+            Strategy::Clangd => self.clangd.fuzzy_indices(haystack, pattern),
+            Strategy::Substring => substring_indices(haystack, pattern, case_sensitive),
+            Strategy::WordPrefix => word_prefix_indices(haystack, pattern, case_sensitive),
+        }
+    }
+}
+
+fn fold(c: char, case_sensitive: bool) -> char {
+    if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) }
+}
+
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    i == 0 || !chars[i - 1].is_alphanumeric() || (chars[i].is_uppercase() && chars[i - 1].is_lowercase())
+}
+
+fn regex_indices(regex: &Regex, haystack: &str) -> Option<(i64, Vec<usize>)> {
+    let m = regex.find(haystack)?;
+    let start = haystack[..m.start()].chars().count();
+    let len = m.as_str().chars().count();
+    Some((100 - start.min(50) as i64 + 2 * len as i64, (start..start + len).collect()))
+}
+
+fn substring_indices(haystack: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+    let chars: Vec<char> = haystack.chars().collect();
+    let needle: Vec<char> = pattern.chars().map(|c| fold(c, case_sensitive)).collect();
+    if needle.len() > chars.len() {
+        return None;
+    }
+    // Prefer a match at a word start over the first one
+    let mut found: Option<usize> = None;
+    for start in 0..=chars.len() - needle.len() {
+        let hit = needle.iter().enumerate().all(|(j, c)| fold(chars[start + j], case_sensitive) == *c);
+        if hit {
+            if is_word_start(&chars, start) {
+                found = Some(start);
+                break;
+            }
+            found = found.or(Some(start));
+        }
+    }
+    let start = found?;
+    let mut score = 20 * needle.len() as i64 - start.min(20) as i64;
+    if start == 0 {
+        score += 30;
+    } else if is_word_start(&chars, start) {
+        score += 15;
+    }
+    Some((score, (start..start + needle.len()).collect()))
+}
+
+// Every piece of the pattern has to be the beginning of a word, in order: "vsc" and "visco" both find "Visual Studio Code"
+fn word_prefix_indices(haystack: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+    let chars: Vec<char> = haystack.chars().collect();
+    let needle: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).map(|c| fold(*c, case_sensitive)).collect();
+    if needle.is_empty() {
+        return None;
+    }
+    let starts: Vec<usize> = (0..chars.len()).filter(|i| chars[*i].is_alphanumeric() && is_word_start(&chars, *i)).collect();
+    let mut indices = Vec::new();
+    if !extend_prefix(&chars, &starts, &needle, case_sensitive, &mut indices) {
+        return None;
+    }
+    let words = indices.iter().filter(|i| starts.contains(i)).count() as i64;
+    let mut score = 25 * needle.len() as i64 - 5 * words;
+    if indices.first() == starts.first() {
+        score += 30;
+    }
+    Some((score, indices))
+}
+
+fn extend_prefix(chars: &[char], starts: &[usize], needle: &[char], case_sensitive: bool, indices: &mut Vec<usize>) -> bool {
+    if needle.is_empty() {
+        return true;
+    }
+    for (w, start) in starts.iter().enumerate() {
+        let common = needle
+            .iter()
+            .zip(&chars[*start..])
+            .take_while(|(n, c)| **n == fold(**c, case_sensitive) && c.is_alphanumeric())
+            .count();
+        // Longest prefix of this word first, then shorter ones
+        for k in (1..=common).rev() {
+            let len = indices.len();
+            indices.extend(*start..*start + k);
+            if extend_prefix(chars, &starts[w + 1..], &needle[k..], case_sensitive, indices) {
+                return true;
+            }
+            indices.truncate(len);
+        }
+    }
+    false
+}
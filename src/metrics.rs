@@ -0,0 +1,144 @@
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+use crate::util::get_color_gradient;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct IconStep {
+    pub below: Option<f64>,
+    pub icon: String,
+}
+
+// Every field is optional so that the user can override a single value
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct MetricConfig {
+    pub warn: Option<f64>,
+    pub crit: Option<f64>,
+    pub gradient_min: Option<f64>,
+    pub gradient_max: Option<f64>,
+    pub reversed: Option<bool>,
+    pub icons: Option<Vec<IconStep>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricRule {
+    pub warn: f64,
+    pub crit: f64,
+    pub gradient_min: f64,
+    pub gradient_max: f64,
+    pub reversed: bool,
+    pub icons: Vec<IconStep>,
+}
+
+// Everything the info grid colors, the keys accepted in [metrics]
+pub const METRIC_IDS: [&str; 8] = ["loadavg", "ram", "disk", "volume", "brightness", "temp", "network", "weather"];
+
+fn step(below: Option<f64>, icon: &str) -> IconStep {
+    IconStep { below, icon: icon.into() }
+}
+
+fn rule(warn: f64, crit: f64, gradient_min: f64, gradient_max: f64, reversed: bool) -> MetricRule {
+    MetricRule { warn, crit, gradient_min, gradient_max, reversed, icons: Vec::new() }
+}
+
+pub fn default_rule(id: &str) -> MetricRule {
+    match id {
+        // load average is evaluated on the m1/m5 ratio
+        "loadavg" => rule(1.2, 2.0, 1.2, 2.0, false),
+        "ram" => rule(60.0, 90.0, 60.0, 90.0, false),
+        "disk" => rule(60.0, 90.0, 60.0, 90.0, false),
+        "volume" => rule(70.0, 100.0, 40.0, 100.0, false),
+        // A bright screen is no problem, it only gets a color unless configured
+        "brightness" => rule(101.0, 101.0, 0.0, 100.0, false),
+        "network" => rule(40.0, 20.0, 20.0, 60.0, true),
+        // Outside temperature, in the unit of the provider
+        "weather" => rule(30.0, 35.0, 0.0, 35.0, false),
+        "temp" => MetricRule {
+            icons: vec![
+                step(Some(80.0), ""),
+                step(Some(85.0), ""),
+                step(Some(90.0), ""),
+                step(Some(95.0), ""),
+                step(None, ""),
+            ],
+            ..rule(80.0, 90.0, 80.0, 99.0, false)
+        },
+        _ => rule(60.0, 90.0, 0.0, 100.0, false),
+    }
+}
+
+impl MetricConfig {
+    fn resolve(&self, id: &str) -> MetricRule {
+        let default = default_rule(id);
+        let mut rule = MetricRule {
+            warn: self.warn.unwrap_or(default.warn),
+            crit: self.crit.unwrap_or(default.crit),
+            gradient_min: self.gradient_min.unwrap_or(default.gradient_min),
+            gradient_max: self.gradient_max.unwrap_or(default.gradient_max),
+            reversed: self.reversed.unwrap_or(default.reversed),
+            icons: self.icons.clone().unwrap_or(default.icons),
+        };
+        // get_color_gradient clamps between the two, which panics unless min <= max
+        if rule.gradient_min.is_nan() || rule.gradient_max.is_nan() {
+            log_warn!("Gradient bounds of metric {} are not numbers, using the defaults", id);
+            rule.gradient_min = default.gradient_min;
+            rule.gradient_max = default.gradient_max;
+        } else if rule.gradient_min > rule.gradient_max {
+            // A descending gradient is the ascending one reversed
+            std::mem::swap(&mut rule.gradient_min, &mut rule.gradient_max);
+            rule.reversed = !rule.reversed;
+        }
+        rule
+    }
+}
+
+pub fn resolve_metrics(configured: &HashMap<String, MetricConfig>, ids: &[&str]) -> HashMap<String, MetricRule> {
+    for id in configured.keys().filter(|id| !ids.contains(&id.as_str())) {
+        log_warn!("Unknown metric \"{}\" in the config, known ones are {}", id, ids.join(", "));
+    }
+    ids.iter()
+        .map(|id| {
+            let rule = configured.get(*id).cloned().unwrap_or_default().resolve(id);
+            (id.to_string(), rule)
+        })
+        .collect()
+}
+
+impl MetricRule {
+    pub fn color(&self, value: f64) -> String {
+        get_color_gradient(self.gradient_min, self.gradient_max, value, self.reversed)
+    }
+
+    // One of the classes InfoView::update_class swaps
+    pub fn state(&self, value: f64) -> &'static str {
+        let over = |threshold: f64| if self.reversed { value <= threshold } else { value >= threshold };
+        if over(self.crit) { "crit" } else if over(self.warn) { "warn" } else { "ok" }
+    }
+
+    pub fn icon(&self, value: f64) -> Option<&str> {
+        self.icons
+            .iter()
+            .find(|s| s.below.map_or(true, |below| value < below))
+            .map(|s| s.icon.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descending_gradient_is_reversed() {
+        let config = MetricConfig { gradient_min: Some(90.0), gradient_max: Some(10.0), ..MetricConfig::default() };
+        let rule = config.resolve("ram");
+        assert_eq!((rule.gradient_min, rule.gradient_max, rule.reversed), (10.0, 90.0, true));
+    }
+
+    #[test]
+    fn nan_gradient_falls_back_to_defaults() {
+        let config = MetricConfig { gradient_min: Some(f64::NAN), ..MetricConfig::default() };
+        let rule = config.resolve("ram");
+        let default = default_rule("ram");
+        assert_eq!((rule.gradient_min, rule.gradient_max), (default.gradient_min, default.gradient_max));
+    }
+}
@@ -2,6 +2,8 @@ use serde_derive::Deserialize;
 use std::process::Command;
 use std::collections::HashMap;
 
+use crate::error::Error;
+
 #[derive(Deserialize)]
 pub struct NiriWindow {
     pub id: u32,
@@ -22,23 +24,23 @@ pub struct NiriWorkspace {
     pub active_window_id: Option<u32>
 }
 
-pub fn get_niri_windows () -> (Vec<NiriWindow>, HashMap<u8, NiriWorkspace>) {
-    // (Vec::new(), HashMap::new())
-    let windows: Vec<NiriWindow>;
-    {
-        let output = Command::new("niri").arg("msg").arg("-j").arg("windows").output();
-        let stdout = String::from_utf8(output.unwrap().stdout).unwrap();
-        // println!("\n{:?}", stdout);
-        windows = serde_json::from_str(&stdout).unwrap();
-    }
-    let workspaces: Vec<NiriWorkspace>;
-    let workspaces_map: HashMap<u8, NiriWorkspace>;
-    {
-        let output = Command::new("niri").arg("msg").arg("-j").arg("workspaces").output();
-        let stdout = String::from_utf8(output.unwrap().stdout).unwrap();
-        // println!("\n{:?}", stdout);
-        workspaces = serde_json::from_str(&stdout).unwrap();
-        workspaces_map = workspaces.into_iter().map(|ws| (ws.id, ws)).collect();
+fn niri_msg<T: serde::de::DeserializeOwned>(what: &str) -> Result<T, Error> {
+    let command = format!("niri msg -j {}", what);
+    let output = Command::new("niri")
+        .arg("msg")
+        .arg("-j")
+        .arg(what)
+        .output()
+        .map_err(|err| Error::command(command.as_str(), err))?;
+    if !output.status.success() {
+        return Err(Error::command(command, String::from_utf8_lossy(&output.stderr).trim()));
     }
-    (windows, workspaces_map)
-}
\ No newline at end of file
+    serde_json::from_slice(&output.stdout).map_err(|err| Error::parse(command, err))
+}
+
+pub fn get_niri_windows() -> Result<(Vec<NiriWindow>, HashMap<u8, NiriWorkspace>), Error> {
+    let windows: Vec<NiriWindow> = niri_msg("windows")?;
+    let workspaces: Vec<NiriWorkspace> = niri_msg("workspaces")?;
+    let workspaces_map = workspaces.into_iter().map(|ws| (ws.id, ws)).collect();
+    Ok((windows, workspaces_map))
+}
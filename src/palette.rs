@@ -0,0 +1,141 @@
+use std::sync::OnceLock;
+
+use crate::util::hsv_to_rgb;
+
+static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+// Stops go from "everything is fine" to "critical"
+pub enum Palette {
+    Default,
+    Stops(Vec<(u8, u8, u8)>),
+}
+
+const COLORBLIND_STOPS: [&str; 4] = ["#FFFFFF", "#FFB000", "#FE6100", "#DC267F"];
+const MONOCHROME_STOPS: [&str; 2] = ["#FFFFFF", "#6E6E6E"];
+
+pub fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+fn parse_stops(stops: &[&str]) -> Vec<(u8, u8, u8)> {
+    stops.iter().filter_map(|s| parse_hex(s)).collect()
+}
+
+impl Palette {
+    pub fn from_config(name: &str, stops: &[String]) -> Palette {
+        match name {
+            "colorblind" => Palette::Stops(parse_stops(&COLORBLIND_STOPS)),
+            "monochrome" => Palette::Stops(parse_stops(&MONOCHROME_STOPS)),
+            "custom" => {
+                let parsed: Vec<(u8, u8, u8)> = stops.iter().filter_map(|s| {
+                    let color = parse_hex(s);
+                    if color.is_none() { log_warn!("Invalid palette stop: {}", s); }
+                    color
+                }).collect();
+                if parsed.len() < 2 {
+                    log_warn!("A custom palette needs at least two stops, using the default one");
+                    Palette::Default
+                } else {
+                    Palette::Stops(parsed)
+                }
+            }
+            "default" => Palette::Default,
+            other => {
+                log_warn!("Unknown palette {}, using the default one", other);
+                Palette::Default
+            }
+        }
+    }
+
+    // severity is 0.0 for the best value and 1.0 for the worst one
+    pub fn color(&self, severity: f64) -> (u8, u8, u8) {
+        let severity = severity.clamp(0.0, 1.0);
+        match self {
+            Palette::Default => {
+                let ratio = 1.0 - severity;
+                let sat = f64::max(1.0 - (ratio * ratio * ratio), 0.0);
+                let hue = 60.0 * ratio; // 120 -> 0
+                hsv_to_rgb(hue, sat, 1.0)
+            }
+            Palette::Stops(stops) => {
+                let position = severity * (stops.len() - 1) as f64;
+                let i = (position.floor() as usize).min(stops.len() - 2);
+                mix_oklab(stops[i], stops[i + 1], position - i as f64)
+            }
+        }
+    }
+
+    pub fn hex(&self, severity: f64) -> String {
+        let (r, g, b) = self.color(severity);
+        format!("#{:02X}{:02X}{:02X}", r, g, b)
+    }
+
+    // Lets style.css use @skoll_ok, @skoll_warn, @skoll_crit and the single stops
+    pub fn css(&self) -> String {
+        let mut css = format!(
+            "@define-color skoll_ok {};\n@define-color skoll_warn {};\n@define-color skoll_crit {};\n",
+            self.hex(0.0), self.hex(0.5), self.hex(1.0)
+        );
+        if let Palette::Stops(stops) = self {
+            for (i, (r, g, b)) in stops.iter().enumerate() {
+                css.push_str(&format!("@define-color skoll_stop_{} #{:02X}{:02X}{:02X};\n", i, r, g, b));
+            }
+        }
+        css
+    }
+}
+
+pub fn set_palette(palette: Palette) {
+    if PALETTE.set(palette).is_err() {
+        log_warn!("Palette already set");
+    }
+}
+
+pub fn current_palette() -> &'static Palette {
+    PALETTE.get_or_init(|| Palette::Default)
+}
+
+fn srgb_to_linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f64) -> u8 {
+    let c = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// https://bottosson.github.io/posts/oklab/
+fn to_oklab((r, g, b): (u8, u8, u8)) -> (f64, f64, f64) {
+    let (r, g, b) = (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    (
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    )
+}
+
+fn from_oklab((l, a, b): (f64, f64, f64)) -> (u8, u8, u8) {
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    (
+        linear_to_srgb(4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_),
+        linear_to_srgb(-1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_),
+        linear_to_srgb(-0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_),
+    )
+}
+
+fn mix_oklab(from: (u8, u8, u8), to: (u8, u8, u8), t: f64) -> (u8, u8, u8) {
+    let (l1, a1, b1) = to_oklab(from);
+    let (l2, a2, b2) = to_oklab(to);
+    from_oklab((l1 + (l2 - l1) * t, a1 + (a2 - a1) * t, b1 + (b2 - b1) * t))
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+// Startup profiling, off unless asked for with --profile[=trace.json] or SKOLL_LOG=profile[=trace.json].
+// Without a file a summary goes to stderr, with one it gets a Chrome trace (chrome://tracing, Perfetto)
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+static PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+
+thread_local! {
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+struct Profile {
+    origin: Instant,
+    output: Option<String>,
+    events: Vec<Event>,
+}
+
+struct Event {
+    name: &'static str,
+    start: Duration,
+    // None for marks
+    duration: Option<Duration>,
+    thread: u64,
+}
+
+pub struct Span {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        record(self.name, self.start, Some(self.start.elapsed()));
+    }
+}
+
+fn profile_option(args: &[String]) -> Option<Option<String>> {
+    let from_env = std::env::var("SKOLL_LOG").ok().and_then(|value| {
+        value.split(',').map(str::trim).find_map(|item| match item {
+            "profile" => Some(None),
+            _ => item.strip_prefix("profile=").map(|file| Some(file.to_string())),
+        })
+    });
+    let from_args = args.iter().find_map(|arg| match arg.as_str() {
+        "--profile" => Some(None),
+        _ => arg.strip_prefix("--profile=").map(|file| Some(file.to_string())),
+    });
+    from_args.or(from_env)
+}
+
+// Turns profiling on when requested and returns the arguments without --profile, which GTK wouldn't know
+pub fn init(args: Vec<String>) -> Vec<String> {
+    if let Some(output) = profile_option(&args) {
+        *PROFILE.lock().unwrap() = Some(Profile { origin: Instant::now(), output, events: Vec::new() });
+        ENABLED.store(true, Ordering::Relaxed);
+    }
+    args.into_iter().filter(|arg| arg != "--profile" && !arg.starts_with("--profile=")).collect()
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Measures until dropped: let _span = profile::span("config_load");
+pub fn span(name: &'static str) -> Span {
+    Span { name, start: Instant::now() }
+}
+
+// A point in time, like the first frame
+pub fn mark(name: &'static str) {
+    record(name, Instant::now(), None);
+}
+
+fn record(name: &'static str, start: Instant, duration: Option<Duration>) {
+    if !enabled() {
+        return;
+    }
+    let thread = THREAD.with(|t| *t);
+    if let Some(profile) = PROFILE.lock().unwrap().as_mut() {
+        let start = start.saturating_duration_since(profile.origin);
+        profile.events.push(Event { name, start, duration, thread });
+    }
+}
+
+pub fn report() {
+    let profile = match PROFILE.lock().unwrap().take() {
+        Some(profile) => profile,
+        None => return,
+    };
+    ENABLED.store(false, Ordering::Relaxed);
+    match &profile.output {
+        Some(file) => {
+            let result = serde_json::to_string(&chrome_trace(&profile))
+                .map_err(|err| err.to_string())
+                .and_then(|s| std::fs::write(file, s).map_err(|err| err.to_string()));
+            match result {
+                Ok(()) => eprintln!("Profile written to {}", file),
+                Err(err) => eprintln!("Cannot write profile to {}: {}", file, err),
+            }
+        }
+        None => eprint!("{}", summary(&profile)),
+    }
+}
+
+fn chrome_trace(profile: &Profile) -> serde_json::Value {
+    let pid = std::process::id();
+    let events: Vec<serde_json::Value> = profile
+        .events
+        .iter()
+        .map(|event| match event.duration {
+            Some(duration) => json!({
+                "name": event.name, "ph": "X", "pid": pid, "tid": event.thread,
+                "ts": event.start.as_micros() as u64, "dur": duration.as_micros() as u64,
+            }),
+            None => json!({
+                "name": event.name, "ph": "i", "s": "g", "pid": pid, "tid": event.thread,
+                "ts": event.start.as_micros() as u64,
+            }),
+        })
+        .collect();
+    json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+}
+
+// One line per span name in order of first appearance, repeated spans (icon passes, keystrokes) are added up
+fn summary(profile: &Profile) -> String {
+    let mut order: Vec<&'static str> = Vec::new();
+    let mut totals: HashMap<&'static str, (Duration, usize, Duration, Option<Duration>)> = HashMap::new();
+    for event in &profile.events {
+        let (first, count, total, max) = totals.entry(event.name).or_insert_with(|| {
+            order.push(event.name);
+            (event.start, 0, Duration::ZERO, None)
+        });
+        *first = (*first).min(event.start);
+        *count += 1;
+        if let Some(duration) = event.duration {
+            *total += duration;
+            *max = Some(max.map_or(duration, |m| m.max(duration)));
+        }
+    }
+    order.sort_by_key(|name| totals[name].0);
+
+    let mut s = format!("{:<24} {:>10} {:>6} {:>12} {:>12}\n", "span", "at", "count", "total", "max");
+    for name in order {
+        let (first, count, total, max) = totals[name];
+        match max {
+            Some(max) => s += &format!("{:<24} {:>10.1?} {:>6} {:>12.1?} {:>12.1?}\n", name, first, count, total, max),
+            None => s += &format!("{:<24} {:>10.1?} {:>6}\n", name, first, count),
+        }
+    }
+    s
+}
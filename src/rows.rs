@@ -0,0 +1,142 @@
+use gtk::builders::{BoxBuilder, ImageBuilder, LabelBuilder};
+use gtk::prelude::*;
+use gtk::{Image, Label, ListBox, ListBoxRow, Orientation};
+use pango::EllipsizeMode;
+use std::convert::TryFrom;
+
+use crate::app_entry::{AppEntry, Sections};
+use crate::config::Config;
+use crate::consts::*;
+use crate::history::HistoryKind;
+use crate::icons::{clear_app_icon, desktop_stamp, icon_key, set_app_icon};
+
+// The list never holds more than max_rows rows: they're built the first time they're needed
+// and then handed to whatever entries are on top after each keystroke
+pub struct RowPool {
+    listbox: ListBox,
+    rows: Vec<PooledRow>,
+    // Entry index shown by each visible row, in list order
+    shown: Vec<usize>,
+    // Section header of each visible row
+    titles: Vec<String>,
+}
+
+struct PooledRow {
+    row: ListBoxRow,
+    image: Image,
+    label: Label,
+    entry: Option<usize>,
+    // Highlight the label attributes were built from, None when they're stale
+    marked: Option<Vec<usize>>,
+}
+
+impl RowPool {
+    pub fn new(listbox: &ListBox) -> RowPool {
+        RowPool { listbox: listbox.clone(), rows: Vec::new(), shown: Vec::new(), titles: Vec::new() }
+    }
+
+    pub fn entry_at(&self, row: &ListBoxRow) -> Option<usize> {
+        usize::try_from(row.index()).ok().and_then(|i| self.shown.get(i)).copied()
+    }
+
+    pub fn header_at(&self, row: &ListBoxRow) -> Option<&str> {
+        usize::try_from(row.index()).ok().and_then(|i| self.titles.get(i)).map(String::as_str)
+    }
+
+    pub fn first_row(&self) -> Option<ListBoxRow> {
+        self.rows.first().filter(|_| !self.shown.is_empty()).map(|pooled| pooled.row.clone())
+    }
+
+    // Headers aren't refreshed here, the header func reads the pool: invalidate them once it's released
+    pub fn sync(&mut self, entries: &[AppEntry], sections: &Sections, query_empty: bool, config: &Config) {
+        let compare = |a: &usize, b: &usize| sections.compare(&entries[*a], &entries[*b], query_empty);
+        let mut visible: Vec<usize> = (0..entries.len()).filter(|i| !entries[*i].hidden()).collect();
+        if config.max_rows > 0 && visible.len() > config.max_rows {
+            visible.select_nth_unstable_by(config.max_rows - 1, compare);
+            visible.truncate(config.max_rows);
+        }
+        visible.sort_by(compare);
+
+        while self.rows.len() < visible.len() {
+            let pooled = PooledRow::new(config);
+            self.listbox.add(&pooled.row);
+            self.rows.push(pooled);
+        }
+        for (pooled, index) in self.rows.iter_mut().zip(&visible) {
+            pooled.show(*index, &entries[*index], config);
+        }
+        for pooled in &self.rows[visible.len()..] {
+            pooled.row.hide();
+        }
+
+        self.titles = if sections.headers {
+            visible.iter().map(|i| sections.header_of(&entries[*i], query_empty)).collect()
+        } else {
+            Vec::new()
+        };
+        self.shown = visible;
+    }
+}
+
+impl PooledRow {
+    fn new(config: &Config) -> PooledRow {
+        let label = LabelBuilder::new()
+            .xalign(0.0f32)
+            .wrap(true)
+            .ellipsize(EllipsizeMode::End)
+            .lines(config.lines)
+            .build();
+        label.style_context().add_class(APP_LABEL_CLASS);
+
+        let image = ImageBuilder::new().pixel_size(config.icon_size).build();
+        image.style_context().add_class(APP_ICON_CLASS);
+
+        let hbox = BoxBuilder::new()
+            .orientation(Orientation::Horizontal)
+            .build();
+        hbox.pack_start(&image, false, false, 0);
+        hbox.pack_end(&label, true, true, 0);
+        hbox.show_all();
+
+        // Unused rows stay hidden through the window's show_all
+        let row = ListBoxRow::new();
+        row.set_no_show_all(true);
+        row.style_context().add_class(APP_ROW_CLASS);
+        row.add(&hbox);
+
+        PooledRow { row, image, label, entry: None, marked: None }
+    }
+
+    fn show(&mut self, index: usize, entry: &AppEntry, config: &Config) {
+        if self.entry != Some(index) {
+            self.label.set_text(&entry.display_string);
+            match &entry.icon {
+                Some(icon) => {
+                    let key = icon_key(entry.info.as_ref(), icon, config.icon_size);
+                    set_app_icon(&self.image, icon, key, desktop_stamp(entry.info.as_ref()), config.icon_size);
+                }
+                None => clear_app_icon(&self.image),
+            }
+            set_class(&self.label, "running", entry.kind == HistoryKind::Focus);
+            set_class(&self.row, "command", entry.kind == HistoryKind::Command);
+            set_class(&self.row, "category", entry.category.is_some());
+            self.entry = Some(index);
+            self.marked = None;
+        }
+        set_class(&self.row, PINNED_ROW_CLASS, entry.pinned.is_some());
+        if self.marked.as_ref() != Some(&entry.highlight) {
+            self.label.set_attributes(Some(&entry.attributes(config)));
+            self.marked = Some(entry.highlight.clone());
+        }
+        self.row.show();
+    }
+}
+
+fn set_class<W: IsA<gtk::Widget>>(widget: &W, class: &str, on: bool) {
+    let context = widget.style_context();
+    if on {
+        context.add_class(class);
+    } else {
+        context.remove_class(class);
+    }
+}
@@ -0,0 +1,94 @@
+use gtk::prelude::*;
+use gtk::{cairo, DrawingArea};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::palette::parse_hex;
+
+// With a sample every 2-4 seconds this covers the last few minutes
+pub const SPARKLINE_SAMPLES: usize = 90;
+
+pub struct MetricHistory {
+    values: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl MetricHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self { values: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, value: f64) -> &Self {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+        self
+    }
+
+    pub fn max(&self) -> f64 {
+        self.values.iter().cloned().fold(0.0, f64::max)
+    }
+
+    // Returns every sample normalized in 0..1 together with the color of its segment
+    pub fn points<F: Fn(f64) -> String>(&self, min: f64, max: f64, color: F) -> Vec<(f64, String)> {
+        self.values.iter().map(|v| {
+            let ratio = if (max - min).abs() < f64::EPSILON { 0.5 } else { (v - min) / (max - min) };
+            (ratio.clamp(0.0, 1.0), color(*v))
+        }).collect()
+    }
+}
+
+fn parse_hex_color(color: &str) -> (f64, f64, f64) {
+    let (r, g, b) = parse_hex(color).unwrap_or((255, 255, 255));
+    (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+}
+
+pub struct Sparkline {
+    pub area: DrawingArea,
+    points: Rc<RefCell<Vec<(f64, String)>>>,
+}
+
+impl Sparkline {
+    pub fn new(width: i32, height: i32) -> Self {
+        let area = DrawingArea::new();
+        area.set_size_request(width, height);
+        area.set_valign(gtk::Align::Center);
+        area.style_context().add_class("sparkline");
+
+        let points: Rc<RefCell<Vec<(f64, String)>>> = Rc::new(RefCell::new(Vec::new()));
+        let points_draw = points.clone();
+        area.connect_draw(move |area, cr| {
+            draw(cr, &points_draw.borrow(), area.allocated_width() as f64, area.allocated_height() as f64);
+            Inhibit(false)
+        });
+
+        Self { area, points }
+    }
+
+    pub fn set_points(&self, points: Vec<(f64, String)>) {
+        self.points.replace(points);
+        self.area.queue_draw();
+    }
+}
+
+fn draw(cr: &cairo::Context, points: &[(f64, String)], width: f64, height: f64) {
+    if points.len() < 2 {
+        return;
+    }
+    // Always spread over the full ring buffer so the line grows from the right
+    let step = width / (SPARKLINE_SAMPLES - 1) as f64;
+    let x0 = width - step * (points.len() - 1) as f64;
+    let y = |ratio: f64| height - 1.0 - ratio * (height - 2.0);
+
+    cr.set_line_width(1.5);
+    for (i, pair) in points.windows(2).enumerate() {
+        let (r, g, b) = parse_hex_color(&pair[1].1);
+        cr.set_source_rgb(r, g, b);
+        cr.move_to(x0 + step * i as f64, y(pair[0].0));
+        cr.line_to(x0 + step * (i + 1) as f64, y(pair[1].0));
+        let _ = cr.stroke();
+    }
+}
@@ -16,12 +16,17 @@ along with sirula.  If not, see <https://www.gnu.org/licenses/>.
 */
 
 use crate::consts::*;
+use crate::error::Error;
+use crate::launch::LaunchContext;
+use crate::palette::{current_palette, Palette};
 use freedesktop_entry_parser::parse_entry;
 use gio::{prelude::AppInfoExt, AppInfo};
 use glib::{shell_parse_argv, GString, ObjectExt};
 use gtk::{prelude::CssProviderExt, CssProvider};
-use std::path::PathBuf;
-use std::process::{id, Command};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{id, Child, Command};
 use shlex::Shlex;
 
 pub fn get_xdg_dirs() -> xdg::BaseDirectories {
@@ -32,20 +37,33 @@ pub fn get_config_file(file: &str) -> Option<PathBuf> {
     get_xdg_dirs().find_config_file(file)
 }
 
-pub fn get_history_file(place: bool) -> Option<PathBuf> {
+pub fn get_cache_file(file: &str, place: bool) -> Option<PathBuf> {
     let xdg = get_xdg_dirs();
     if place {
-        xdg.place_cache_file(HISTORY_FILE).ok()
+        xdg.place_cache_file(file).ok()
     } else {
-        xdg.find_cache_file(HISTORY_FILE)
+        xdg.find_cache_file(file)
     }
 }
 
+pub fn get_history_file(place: bool) -> Option<PathBuf> {
+    get_cache_file(HISTORY_FILE, place)
+}
+
+// Readers see the old file or the new one, never half of it
+pub fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
 pub fn load_css() {
     if let Some(file) = get_config_file(STYLE_FILE) {
         let provider = CssProvider::new();
         if let Err(err) = provider.load_from_path(file.to_str().unwrap()) {
-            eprintln!("Failed to load CSS: {}", err);
+            log_warn!("Failed to load CSS: {}", err);
         }
         gtk::StyleContext::add_provider_for_screen(
             &gdk::Screen::default().expect("Error initializing gtk css provider."),
@@ -64,27 +82,40 @@ pub fn load_css() {
     }
 }
 
+pub fn load_palette_css(palette: &Palette) {
+    let provider = CssProvider::new();
+    if let Err(err) = provider.load_from_data(palette.css().as_bytes()) {
+        log_warn!("Failed to load palette CSS: {}", err);
+        return;
+    }
+    gtk::StyleContext::add_provider_for_screen(
+        &gdk::Screen::default().expect("Error initializing gtk css provider."),
+        &provider,
+        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
+}
+
 pub fn is_cmd(text: &str, cmd_prefix: &str) -> bool {
     !cmd_prefix.is_empty() && text.starts_with(cmd_prefix)
 }
 
-pub fn launch_cmd(cmd_line: &str) {
-    let mut parts = shell_parse_argv(cmd_line).expect("Error parsing command line");
+pub fn launch_cmd(cmd_line: &str, ctx: &LaunchContext) -> Result<Child, Error> {
+    let mut parts = shell_parse_argv(cmd_line).map_err(|err| Error::parse(format!("command {}", cmd_line), err))?;
     let mut parts_iter = parts.iter_mut();
 
-    let cmd = parts_iter.next().expect("Expected command");
+    let cmd = parts_iter.next().ok_or_else(|| Error::launch(cmd_line, "empty command"))?;
 
     let mut child = Command::new(cmd);
     child.args(parts_iter);
-    child.spawn().expect("Error spawning command");
+    ctx.spawn(&mut child).map_err(|err| Error::launch(cmd_line, err))
 }
 
-pub fn launch_app(info: &AppInfo, term_command: Option<&str>, launch_cgroups: bool) {
+pub fn launch_app(info: &AppInfo, term_command: Option<&str>, launch_cgroups: bool, ctx: &LaunchContext) -> Result<Child, Error> {
+    let name = info.display_name().to_string();
     let command_string = info
         .commandline()
         .unwrap_or_else(|| info.executable())
-        .to_str()
-        .unwrap()
+        .to_string_lossy()
         .to_string()
         .replace("%U", "")
         .replace("%F", "")
@@ -107,36 +138,44 @@ pub fn launch_app(info: &AppInfo, term_command: Option<&str>, launch_cgroups: bo
             let command_string = term.to_string().replace("{}", &command_string);
 		    command = Shlex::new(&command_string).collect();
         } else if let Some(term) = std::env::var_os("TERMINAL") {
-        	let term = term.into_string().expect("couldn't convert to string");
+        	let term = term.to_string_lossy().to_string();
         	let mut command_new = vec![term, "-e".into()];
         	command_new.extend(command);
         	command = command_new;
         } else {
-            return;
+            return Err(Error::launch(name, "it needs a terminal, set term_command or $TERMINAL"));
         };
     }
     if launch_cgroups {
-        let mut name = info.id().unwrap().to_string();
-        name.truncate(name.len() - 8); // remove .desktop extension
-        let parsed = Command::new("systemd-escape")
-            .arg(name)
-            .output()
-            .unwrap()
-            .stdout;
-        let unit = format!(
-            "--unit=app-skoll-{}-{}",
-            String::from_utf8_lossy(&parsed).trim(),
-            id()
-        );
-        let mut command_new: Vec<String> = vec!["systemd-run".into(), "--scope".into(), "--user".into(), unit];
-        command_new.extend(command);
-        command = command_new;
+        // Without an id or systemd-escape the app still starts, just outside its own scope
+        match info.id().map(|id| systemd_unit(&id)) {
+            Some(Ok(unit)) => {
+                let mut command_new: Vec<String> = vec!["systemd-run".into(), "--scope".into(), "--user".into(), unit];
+                command_new.extend(command);
+                command = command_new;
+            }
+            Some(Err(err)) => log_warn!("{}", err),
+            None => log_warn!("{} has no desktop id, launching it without a cgroup", name),
+        }
+    }
+    if command.is_empty() {
+        return Err(Error::launch(name, "empty command line"));
     }
 
-    Command::new(&command[0])
-        .args(&command[1..])
-        .spawn()
-        .expect("Error launching app");
+    log_info!("Launching {}: {}", name, command.join(" "));
+    ctx.spawn(Command::new(&command[0]).args(&command[1..])).map_err(|err| Error::launch(name, err))
+}
+
+fn systemd_unit(desktop_id: &str) -> Result<String, Error> {
+    let name = desktop_id.strip_suffix(".desktop").unwrap_or(desktop_id);
+    let output = Command::new("systemd-escape")
+        .arg(name)
+        .output()
+        .map_err(|err| Error::command("systemd-escape", err))?;
+    if !output.status.success() {
+        return Err(Error::command("systemd-escape", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(format!("--unit=app-skoll-{}-{}", String::from_utf8_lossy(&output.stdout).trim(), id()))
 }
 
 #[macro_export]
@@ -157,7 +196,7 @@ macro_rules! clone {
     );
 }
 
-fn hsv_to_rgb(h: f64, s: f64, v: f64) -> (u8, u8, u8) {
+pub fn hsv_to_rgb(h: f64, s: f64, v: f64) -> (u8, u8, u8) {
     let c = v * s;
     let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
     let m = v - c;
@@ -186,12 +225,8 @@ pub fn get_color_gradient(min: f64, max: f64, value: f64, reversed: bool) -> Str
         (clamped - min) / (max - min)
     };
 
-    if !reversed { ratio = 1.0 - ratio; }
-    let sat = f64::max(1.0 - (ratio * ratio * ratio), 0.0);
-    let hue = 60.0 * ratio; // 120 -> 0
-    let (r, g, b) = hsv_to_rgb(hue, sat, 1.0);
-
-    format!("#{:02X}{:02X}{:02X}", r, g, b)
+    if reversed { ratio = 1.0 - ratio; }
+    current_palette().hex(ratio)
 }
 
 /* pub fn apply_scale_color(scale: &gtk::Scale, hex_color: &str) {
@@ -0,0 +1,409 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::consts::WEATHER_CACHE_FILE;
+use crate::error::Error;
+use crate::icons::IMAGE_EXTENSIONS;
+use crate::util::{get_xdg_dirs, write_atomic};
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct WeatherObj {
+    pub icon: String,
+    pub icon_name: String,
+    pub temp: i8,
+    pub temp_real: i8,
+    pub temp_unit: String,
+    pub text: String,
+    pub day: String,
+    pub sunrise: String,
+    pub sunset: String,
+    pub sunrise_mins: u64,
+    pub sunset_mins: u64,
+    pub daylight: f64,
+    pub locality: String,
+    pub humidity: u8
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WeatherConfig {
+    pub provider: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub location: Option<String>,
+    pub base_url: String,
+    pub geocoding_url: String,
+    pub fahrenheit: bool,
+    // seconds, both for the cache validity and for the refresh
+    pub ttl: u64,
+    pub script: Option<String>,
+    pub icons_dir: Option<String>,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        WeatherConfig {
+            provider: "open-meteo".into(),
+            latitude: None,
+            longitude: None,
+            location: None,
+            base_url: "https://api.open-meteo.com".into(),
+            geocoding_url: "https://geocoding-api.open-meteo.com".into(),
+            fahrenheit: false,
+            ttl: 1800,
+            script: None,
+            icons_dir: None,
+        }
+    }
+}
+
+pub trait WeatherProvider: Send {
+    // Used to invalidate the cache when the location or the provider change
+    fn cache_key(&self) -> String;
+    fn fetch(&self) -> Result<WeatherObj, Error>;
+}
+
+impl WeatherConfig {
+    // Without a place (or a script) every fetch would fail, better no weather at all
+    pub fn is_configured(&self) -> bool {
+        match self.provider.as_str() {
+            "script" => self.script.is_some(),
+            _ => self.location.is_some() || (self.latitude.is_some() && self.longitude.is_some()),
+        }
+    }
+}
+
+pub fn provider_from_config(config: &WeatherConfig) -> Option<Box<dyn WeatherProvider>> {
+    if !config.is_configured() {
+        return None;
+    }
+    Some(match config.provider.as_str() {
+        "script" => Box::new(ScriptProvider { config: config.clone() }),
+        "open-meteo" => Box::new(OpenMeteo { config: config.clone() }),
+        other => {
+            log_warn!("Unknown weather provider {}, using open-meteo", other);
+            Box::new(OpenMeteo { config: config.clone() })
+        }
+    })
+}
+
+// Runs curl, which has to be on PATH for the open-meteo provider (and for its tests)
+fn http_get(url: &str) -> Result<String, Error> {
+    let output = Command::new("curl")
+        .args(["-sSf", "--max-time", "10", url])
+        .output()
+        .map_err(|err| Error::command("curl", err))?;
+    if !output.status.success() {
+        return Err(Error::command(format!("Request to {}", url), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    String::from_utf8(output.stdout).map_err(|err| Error::parse(url, err))
+}
+
+pub struct OpenMeteo {
+    config: WeatherConfig,
+}
+
+impl OpenMeteo {
+    fn coordinates(&self) -> Result<(f64, f64, String), Error> {
+        if let (Some(lat), Some(lon)) = (self.config.latitude, self.config.longitude) {
+            let name = self.config.location.clone().unwrap_or_else(|| format!("{:.2}, {:.2}", lat, lon));
+            return Ok((lat, lon, name));
+        }
+        let name = self.config.location.as_ref().ok_or_else(|| Error::command("open-meteo", "no weather location configured"))?;
+        let url = format!(
+            "{}/v1/search?name={}&count=1",
+            self.config.geocoding_url.trim_end_matches('/'),
+            glib::uri_escape_string(name, None, false)
+        );
+        let data: Value = serde_json::from_str(&http_get(&url)?).map_err(|err| Error::parse("geocoding response", err))?;
+        let place = &data["results"][0];
+        match (place["latitude"].as_f64(), place["longitude"].as_f64()) {
+            (Some(lat), Some(lon)) => Ok((lat, lon, place["name"].as_str().unwrap_or(name).to_string())),
+            _ => Err(Error::command("open-meteo", format!("location {} not found", name))),
+        }
+    }
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn cache_key(&self) -> String {
+        format!(
+            "open-meteo:{:?}:{:?}:{:?}:{}",
+            self.config.latitude, self.config.longitude, self.config.location, self.config.fahrenheit
+        )
+    }
+
+    fn fetch(&self) -> Result<WeatherObj, Error> {
+        let (lat, lon, locality) = self.coordinates()?;
+        let url = format!(
+            "{}/v1/forecast?latitude={}&longitude={}&current=temperature_2m,apparent_temperature,relative_humidity_2m,weather_code,is_day&daily=sunrise,sunset,daylight_duration&timezone=auto&forecast_days=1&temperature_unit={}",
+            self.config.base_url.trim_end_matches('/'),
+            lat,
+            lon,
+            if self.config.fahrenheit { "fahrenheit" } else { "celsius" }
+        );
+        let data: Value = serde_json::from_str(&http_get(&url)?).map_err(|err| Error::parse("weather data", err))?;
+        parse_open_meteo(&data, locality)
+    }
+}
+
+// "2025-06-01T05:35" -> ("05:35", 335)
+fn parse_time(iso: &str) -> (String, u64) {
+    let time = iso.split('T').nth(1).unwrap_or(iso).to_string();
+    let mut parts = time.split(':').map(|p| p.parse::<u64>().unwrap_or(0));
+    let mins = parts.next().unwrap_or(0) * 60 + parts.next().unwrap_or(0);
+    (time, mins)
+}
+
+pub fn parse_open_meteo(data: &Value, locality: String) -> Result<WeatherObj, Error> {
+    let current = &data["current"];
+    let temp = current["temperature_2m"].as_f64().ok_or_else(|| Error::parse("weather data", "no temperature"))?;
+    let code = current["weather_code"].as_u64().ok_or_else(|| Error::parse("weather data", "no weather code"))?;
+    let is_day = current["is_day"].as_u64().unwrap_or(1) == 1;
+    let (sunrise, sunrise_mins) = parse_time(data["daily"]["sunrise"][0].as_str().unwrap_or(""));
+    let (sunset, sunset_mins) = parse_time(data["daily"]["sunset"][0].as_str().unwrap_or(""));
+    let (icon, icon_name, text) = describe_weather_code(code, is_day);
+
+    Ok(WeatherObj {
+        icon: icon.into(),
+        icon_name: icon_name.into(),
+        temp: temp.round() as i8,
+        temp_real: current["apparent_temperature"].as_f64().unwrap_or(temp).round() as i8,
+        temp_unit: data["current_units"]["temperature_2m"].as_str().unwrap_or("°C").into(),
+        text: text.into(),
+        day: if is_day { "day".into() } else { "night".into() },
+        sunrise,
+        sunset,
+        sunrise_mins,
+        sunset_mins,
+        // hours, like the old script
+        daylight: data["daily"]["daylight_duration"][0].as_f64().unwrap_or(0.0) / 3600.0,
+        locality,
+        humidity: current["relative_humidity_2m"].as_u64().unwrap_or(0) as u8,
+    })
+}
+
+// WMO weather interpretation codes, icon_name follows the freedesktop icon naming spec
+fn describe_weather_code(code: u64, is_day: bool) -> (&'static str, &'static str, &'static str) {
+    match code {
+        0 if is_day => ("󰖙", "weather-clear", "Clear sky"),
+        0 => ("󰖔", "weather-clear-night", "Clear sky"),
+        1 | 2 if is_day => ("󰖕", "weather-few-clouds", "Partly cloudy"),
+        1 | 2 => ("󰼱", "weather-few-clouds-night", "Partly cloudy"),
+        3 => ("󰖐", "weather-overcast", "Overcast"),
+        45 | 48 => ("󰖑", "weather-fog", "Fog"),
+        51..=57 => ("󰖗", "weather-showers-scattered", "Drizzle"),
+        61..=67 => ("󰖖", "weather-showers", "Rain"),
+        71..=77 => ("󰖘", "weather-snow", "Snow"),
+        80..=82 => ("󰖖", "weather-showers", "Rain showers"),
+        85 | 86 => ("󰙿", "weather-snow", "Snow showers"),
+        95..=99 => ("󰖓", "weather-storm", "Thunderstorm"),
+        _ => ("󰖐", "weather-severe-alert", "Unknown"),
+    }
+}
+
+// icon_name has no extension, the first image with a known one wins
+pub fn weather_icon_file(dir: &str, icon_name: &str) -> Option<PathBuf> {
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|ext| Path::new(dir).join(format!("{}{}", icon_name, ext)))
+        .find(|file| file.is_file())
+}
+
+pub struct ScriptProvider {
+    config: WeatherConfig,
+}
+
+impl WeatherProvider for ScriptProvider {
+    fn cache_key(&self) -> String {
+        format!("script:{:?}:{:?}", self.config.script, self.config.location)
+    }
+
+    fn fetch(&self) -> Result<WeatherObj, Error> {
+        let script = self.config.script.as_ref().ok_or_else(|| Error::command("weather script", "none configured"))?;
+        let mut command = Command::new(script);
+        if let Some(location) = &self.config.location {
+            command.arg(location);
+        }
+        if let (Some(lat), Some(lon)) = (self.config.latitude, self.config.longitude) {
+            command.arg(lat.to_string()).arg(lon.to_string());
+        }
+        let output = command.output().map_err(|err| Error::command(script.as_str(), err))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        serde_json::from_str(&stdout).map_err(|err| Error::parse(format!("output of {}", script), err))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct WeatherCache {
+    key: String,
+    fetched_at: u64,
+    weather: WeatherObj,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn load_cache(file: &Path, key: &str) -> Option<WeatherCache> {
+    let cache: WeatherCache = serde_json::from_str(&std::fs::read_to_string(file).ok()?).ok()?;
+    if cache.key == key { Some(cache) } else { None }
+}
+
+fn save_cache(file: &Path, key: &str, weather: &WeatherObj) {
+    let cache = WeatherCache { key: key.into(), fetched_at: now(), weather: weather.clone() };
+    match serde_json::to_string(&cache) {
+        Ok(s) => {
+            if let Err(err) = write_atomic(file, &s) {
+                log_warn!("Cannot write weather cache: {}", err);
+            }
+        }
+        Err(err) => log_warn!("Cannot serialize weather cache: {}", err),
+    }
+}
+
+pub fn get_weather(provider: &dyn WeatherProvider, ttl: u64) -> Result<WeatherObj, Error> {
+    match get_xdg_dirs().place_cache_file(WEATHER_CACHE_FILE) {
+        Ok(file) => get_weather_cached(provider, ttl, &file),
+        Err(err) => {
+            log_warn!("Cannot create weather cache: {}", err);
+            provider.fetch()
+        }
+    }
+}
+
+// Fresh cache wins, otherwise ask the provider and fall back to stale data when offline
+fn get_weather_cached(provider: &dyn WeatherProvider, ttl: u64, file: &Path) -> Result<WeatherObj, Error> {
+    let key = provider.cache_key();
+    let cached = load_cache(file, &key);
+    if let Some(cache) = &cached {
+        if now().saturating_sub(cache.fetched_at) < ttl {
+            return Ok(cache.weather.clone());
+        }
+    }
+    match provider.fetch() {
+        Ok(weather) => {
+            save_cache(file, &key, &weather);
+            Ok(weather)
+        }
+        Err(err) => match cached {
+            Some(cache) => {
+                log_warn!("Weather update failed, using cached data: {}", err);
+                Ok(cache.weather)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const FORECAST: &str = r#"{
+        "current_units": {"temperature_2m": "°C"},
+        "current": {"temperature_2m": 21.4, "apparent_temperature": 20.6, "relative_humidity_2m": 55, "weather_code": 3, "is_day": 1},
+        "daily": {"sunrise": ["2025-06-01T05:35"], "sunset": ["2025-06-01T21:02"], "daylight_duration": [55620.0]}
+    }"#;
+
+    // Answers every request with the canned forecast, returns its base url and how many requests it got
+    fn stub_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf);
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    FORECAST.len(),
+                    FORECAST
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        (url, requests)
+    }
+
+    // Nothing listens there anymore
+    fn closed_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    fn provider(base_url: String) -> OpenMeteo {
+        OpenMeteo {
+            config: WeatherConfig {
+                latitude: Some(45.0),
+                longitude: Some(9.0),
+                location: Some("Testville".into()),
+                base_url,
+                ..WeatherConfig::default()
+            },
+        }
+    }
+
+    fn cache_file(name: &str) -> PathBuf {
+        let file = std::env::temp_dir().join(format!("skoll-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        file
+    }
+
+    #[test]
+    fn parses_open_meteo_forecast() {
+        let (url, _) = stub_server();
+        let weather = provider(url).fetch().unwrap();
+        assert_eq!(weather.temp, 21);
+        assert_eq!(weather.temp_real, 21);
+        assert_eq!(weather.temp_unit, "°C");
+        assert_eq!(weather.text, "Overcast");
+        assert_eq!(weather.icon_name, "weather-overcast");
+        assert_eq!(weather.day, "day");
+        assert_eq!((weather.sunrise.as_str(), weather.sunrise_mins), ("05:35", 335));
+        assert_eq!((weather.sunset.as_str(), weather.sunset_mins), ("21:02", 1262));
+        assert!((weather.daylight - 15.45).abs() < 1e-9);
+        assert_eq!(weather.humidity, 55);
+        assert_eq!(weather.locality, "Testville");
+    }
+
+    #[test]
+    fn fresh_cache_skips_the_request() {
+        let (url, requests) = stub_server();
+        let file = cache_file("weather-ttl");
+        let provider = provider(url);
+        get_weather_cached(&provider, 3600, &file).unwrap();
+        get_weather_cached(&provider, 3600, &file).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        // An expired cache is fetched again
+        get_weather_cached(&provider, 0, &file).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn stale_cache_is_used_when_the_server_is_down() {
+        let (url, _) = stub_server();
+        let file = cache_file("weather-stale");
+        get_weather_cached(&provider(url), 0, &file).unwrap();
+        let weather = get_weather_cached(&provider(closed_url()), 0, &file).unwrap();
+        assert_eq!(weather.temp, 21);
+        let _ = std::fs::remove_file(&file);
+        assert!(get_weather_cached(&provider(closed_url()), 0, &file).is_err());
+    }
+
+    #[test]
+    fn no_provider_without_a_location() {
+        assert!(provider_from_config(&WeatherConfig::default()).is_none());
+        let config = WeatherConfig { location: Some("Testville".into()), ..WeatherConfig::default() };
+        assert!(provider_from_config(&config).is_some());
+    }
+}